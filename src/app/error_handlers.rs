@@ -0,0 +1,203 @@
+use std::{any::Any, collections::HashMap, path::PathBuf, sync::Arc};
+
+use crate::http::{
+    http_method::HttpMethod,
+    response::{HttpStatusCode, Response},
+};
+
+/// A handler that gets the default error response and returns the response to send
+pub type ErrorHandler = Arc<dyn Fn(Response) -> Response + Send + Sync + 'static>;
+
+/// A hook that gets every panic caught while handling a request, ex; to report it to a monitoring service
+pub type PanicHook = Arc<dyn Fn(&PanicReport) + Send + Sync + 'static>;
+
+///
+/// What we know about a panic caught while handling a request
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    pub method: HttpMethod,
+
+    /// the request path
+    pub path: String,
+
+    /// the path the matched route is registered with, None if no route matched the request
+    pub route: Option<String>,
+
+    /// the panic message, ex; `called `Option::unwrap()` on a `None` value`
+    pub message: String,
+}
+
+impl PanicReport {
+    pub fn new(
+        method: HttpMethod,
+        path: String,
+        route: Option<String>,
+        payload: &(dyn Any + Send),
+    ) -> Self {
+        // `panic!` payloads are `&str` or `String` unless `panic_any` is used
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.to_owned(),
+            _ => "Box<dyn Any>".to_owned(),
+        };
+
+        Self {
+            method,
+            path,
+            route,
+            message,
+        }
+    }
+}
+
+///
+/// The responses the server sends when it can not get one from a route handler
+///
+/// This covers requests that can not be parsed (400, 413..), requests that do not match
+/// any route (404, 405), and route handlers that failed (500).
+///
+#[derive(Clone, Default)]
+pub struct ErrorHandlers {
+    /// the registered handlers by status code
+    handlers: HashMap<u16, ErrorHandler>,
+
+    /// the page sent as the body of 404 responses, if any
+    not_found_page: Option<PathBuf>,
+
+    /// the hook called for every caught panic, if any
+    panic_hook: Option<PanicHook>,
+}
+
+impl ErrorHandlers {
+    pub fn set_handler(&mut self, status_code: HttpStatusCode, handler: ErrorHandler) {
+        self.handlers.insert(status_code.get_code(), handler);
+    }
+
+    pub fn set_not_found_page(&mut self, path: PathBuf) {
+        self.not_found_page = Some(path);
+    }
+
+    pub fn set_panic_hook(&mut self, hook: PanicHook) {
+        self.panic_hook = Some(hook);
+    }
+
+    ///
+    /// Log a panic caught while handling a request and pass it to the panic hook
+    ///
+    pub fn report_panic(&self, report: &PanicReport) {
+        eprintln!(
+            "Route handler `{} {}` panicked: {}",
+            report.method.as_str(),
+            report.route.as_deref().unwrap_or(&report.path),
+            report.message
+        );
+
+        if let Some(hook) = &self.panic_hook {
+            hook(report);
+        }
+    }
+
+    ///
+    /// Build the error response of a status code, using the registered handler if any
+    ///
+    pub fn response(&self, status_code: HttpStatusCode) -> Response {
+        let response = match (&status_code, &self.not_found_page) {
+            (HttpStatusCode::NotFound, Some(page)) => {
+                Response::from_file(page, HttpStatusCode::NotFound)
+                    .unwrap_or_else(|_| Response::not_found())
+            }
+            _ => Response::new(status_code, ""),
+        };
+
+        self.handle(response)
+    }
+
+    ///
+    /// Pass an error response to the handler registered for its status code, if any
+    ///
+    pub fn handle(&self, response: Response) -> Response {
+        match self.handlers.get(&response.status_code.get_code()) {
+            Some(handler) => handler(response),
+            None => response,
+        }
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn default_response_has_the_status_code() {
+        let response = ErrorHandlers::default().response(HttpStatusCode::BadRequest);
+
+        assert_eq!(response.status_code, HttpStatusCode::BadRequest);
+        assert_eq!(response.body.len(), Some(0));
+    }
+
+    #[test]
+    fn registered_handler_builds_the_response() {
+        let mut errors = ErrorHandlers::default();
+        errors.set_handler(
+            HttpStatusCode::ServerError,
+            Arc::new(|mut response: Response| {
+                response.set_header("Content-Type", "application/json");
+                response.with_body("{\"error\":\"server error\"}")
+            }),
+        );
+
+        let response = errors.response(HttpStatusCode::ServerError);
+
+        assert_eq!(response.status_code, HttpStatusCode::ServerError);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("application/json")
+        );
+        assert_eq!(
+            response.body.as_bytes(),
+            Some("{\"error\":\"server error\"}".as_bytes())
+        );
+    }
+
+    #[test]
+    fn panic_message_is_extracted_from_the_payload() {
+        let report = |payload: Box<dyn Any + Send>| {
+            PanicReport::new(HttpMethod::Get, "/".to_owned(), None, payload.as_ref()).message
+        };
+
+        assert_eq!(report(Box::new("static message")), "static message");
+        assert_eq!(report(Box::new(format!("user {}", 1))), "user 1");
+        assert_eq!(report(Box::new(42)), "Box<dyn Any>");
+    }
+
+    #[test]
+    fn not_found_page_is_the_default_404_body() {
+        let mut errors = ErrorHandlers::default();
+        errors.set_not_found_page(PathBuf::from("static/404.html"));
+
+        let response = errors.response(HttpStatusCode::NotFound);
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn missing_not_found_page_falls_back_to_an_empty_body() {
+        let mut errors = ErrorHandlers::default();
+        errors.set_not_found_page(PathBuf::from("static/missing.html"));
+
+        let response = errors.response(HttpStatusCode::NotFound);
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(response.get_header("Content-Type"), None);
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+
+use crate::http::{
+    cookie_jar::CookieJar,
+    request::Request,
+    response::{HttpStatusCode, Response},
+};
+
+use self::params_deserializer::ParamsDeserializer;
+
+use super::session::Session;
+
+pub mod params_deserializer;
+
+///
+/// A value that can be taken from a request, it can be used as a route handler argument
+///
+/// ex; `App::default().get("/users/{id}", |Path(id): Path<u32>| Response::ok(&id.to_string()))`.
+/// If the value can not be taken from the request the handler is not called
+/// and the request gets the error response.
+///
+pub trait FromRequest: Sized {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError>;
+}
+
+///
+/// Why a value could not be taken from a request, it's sent to the client as a plain text response
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractionError {
+    pub status_code: HttpStatusCode,
+    pub message: String,
+}
+
+impl ExtractionError {
+    pub fn new(status_code: HttpStatusCode, message: &str) -> Self {
+        Self {
+            status_code,
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(HttpStatusCode::BadRequest, message)
+    }
+
+    pub fn into_response(self) -> Response {
+        let mut response = Response::new(self.status_code, &self.message);
+        response.set_header("Content-Type", "text/plain; charset=utf-8");
+        response
+    }
+}
+
+impl Display for ExtractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+///
+/// The route params deserialized into `T`
+///
+/// `T` can be a single value for routes with one param (`Path<u32>`),
+/// a tuple of the params in order (`Path<(u32, String)>`), or a struct with a field per param.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        T::deserialize(ParamsDeserializer::new(&request.route_params))
+            .map(Path)
+            .map_err(|e| ExtractionError::bad_request(&format!("Invalid route params: {}", e)))
+    }
+}
+
+///
+/// The query string deserialized into `T`, usually a struct with a field per query param
+///
+/// Use `Option` fields for the params that can be missing.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        let query = match request.full_path.split_once('?') {
+            Some((_, query)) => query,
+            None => "",
+        };
+
+        serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|e| ExtractionError::bad_request(&format!("Invalid query string: {}", e)))
+    }
+}
+
+impl FromRequest for CookieJar {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        Ok(CookieJar::from_request(request))
+    }
+}
+
+///
+/// The request session, it fails with 500 Internal Server Error if the app does not use sessions
+///
+impl FromRequest for Session {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        request.session().cloned().ok_or_else(|| {
+            ExtractionError::new(
+                HttpStatusCode::ServerError,
+                "Sessions are not enabled, see `App::sessions`",
+            )
+        })
+    }
+}
+
+///
+/// The URL-encoded form body deserialized into `T`, see `Request::form`
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        request.form().map(Form)
+    }
+}
+
+///
+/// The request body deserialized from JSON into `T`, see `Request::json`
+///
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &Request) -> Result<Self, ExtractionError> {
+        request.json().map(Json)
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{
+        app::{
+            route::{IntoRouteHandler, Route},
+            router::Router,
+        },
+        http::http_method::HttpMethod,
+    };
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+    }
+
+    async fn request(path: &str, raw: &str) -> Request {
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        let mut router = Router::default();
+        router.insert(Route::new(HttpMethod::Post, path.to_owned(), handler));
+
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        let route = router.find(&request.method, &request.base_path);
+        request
+            .complete_parsing(&mut reader, route.as_ref(), 1024)
+            .await
+            .unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn path_is_extracted() {
+        let request = request("/users/{id}", "POST /users/7 HTTP/1.1\r\n\r\n").await;
+
+        assert_eq!(Path::<u32>::from_request(&request), Ok(Path(7)));
+    }
+
+    #[tokio::test]
+    async fn optional_path_segment_is_extracted() {
+        let with_page = request("/posts/{page?}", "POST /posts/2 HTTP/1.1\r\n\r\n").await;
+        assert_eq!(
+            Path::<Option<u32>>::from_request(&with_page),
+            Ok(Path(Some(2)))
+        );
+
+        let without_page = request("/posts/{page?}", "POST /posts HTTP/1.1\r\n\r\n").await;
+        assert_eq!(
+            Path::<Option<u32>>::from_request(&without_page),
+            Ok(Path(None))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_path_is_a_bad_request() {
+        let request = request("/users/{id}", "POST /users/me HTTP/1.1\r\n\r\n").await;
+
+        let error = Path::<u32>::from_request(&request).unwrap_err();
+
+        assert_eq!(error.status_code, HttpStatusCode::BadRequest);
+        assert_eq!(
+            error.message,
+            "Invalid route params: `id` param must be a positive integer, got `me`"
+        );
+    }
+
+    #[tokio::test]
+    async fn query_is_extracted() {
+        let request = request("/search", "POST /search?q=rust%20server HTTP/1.1\r\n\r\n").await;
+
+        assert_eq!(
+            Query::<Search>::from_request(&request),
+            Ok(Query(Search {
+                q: "rust server".to_owned(),
+                page: None
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_query_is_a_bad_request() {
+        let request = request("/search", "POST /search?q=rust&page=two HTTP/1.1\r\n\r\n").await;
+
+        let error = Query::<Search>::from_request(&request).unwrap_err();
+
+        assert_eq!(error.status_code, HttpStatusCode::BadRequest);
+        assert!(error.message.starts_with("Invalid query string: "));
+    }
+
+    #[tokio::test]
+    async fn form_body_is_extracted() {
+        let request = request(
+            "/search",
+            "POST /search HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 20\r\n\r\nq=rust+server&page=2",
+        )
+        .await;
+
+        assert_eq!(
+            Form::<Search>::from_request(&request),
+            Ok(Form(Search {
+                q: "rust server".to_owned(),
+                page: Some(2)
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn encoded_path_is_extracted_decoded() {
+        let request = request(
+            "/tags/{tag}",
+            "POST /tags/caf%C3%A9%20au%20lait HTTP/1.1\r\n\r\n",
+        )
+        .await;
+
+        assert_eq!(
+            Path::<String>::from_request(&request),
+            Ok(Path("café au lait".to_owned()))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_body_is_extracted() {
+        let request = request(
+            "/search",
+            "POST /search HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 24\r\n\r\n{\"q\": \"rust\", \"page\": 2}",
+        )
+        .await;
+
+        assert_eq!(
+            Json::<Search>::from_request(&request),
+            Ok(Json(Search {
+                q: "rust".to_owned(),
+                page: Some(2)
+            }))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn invalid_json_body_is_a_bad_request() {
+        let request = request(
+            "/search",
+            "POST /search HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 9\r\n\r\n{\"page\":}",
+        )
+        .await;
+
+        let error = Json::<Search>::from_request(&request).unwrap_err();
+
+        assert_eq!(error.status_code, HttpStatusCode::BadRequest);
+        assert!(error.message.starts_with("Invalid JSON body: "));
+    }
+
+    #[test]
+    fn extraction_error_is_sent_as_plain_text() {
+        let response = ExtractionError::bad_request("Invalid route params").into_response();
+
+        assert_eq!(response.status_code, HttpStatusCode::BadRequest);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(
+            response.body.as_bytes(),
+            Some("Invalid route params".as_bytes())
+        );
+    }
+}
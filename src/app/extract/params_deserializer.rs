@@ -0,0 +1,373 @@
+use serde::de::{
+    self,
+    value::{Error, StrDeserializer},
+    DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+
+use crate::http::request_param::RequestParam;
+
+///
+/// Deserialize the route params into a value
+///
+/// - structs and maps get the params by name, ex; `/users/{id}` into `struct User { id: u32 }`
+/// - tuples and sequences get the params in the order they appear in the path
+/// - any other type needs the route to have exactly one param, ex; `/users/{id}` into `u32`
+///
+/// Param values are parsed into the requested type, so `{id}` can be deserialized as a number.
+///
+pub struct ParamsDeserializer<'a> {
+    params: &'a [RequestParam],
+}
+
+impl<'a> ParamsDeserializer<'a> {
+    pub fn new(params: &'a [RequestParam]) -> Self {
+        Self { params }
+    }
+
+    /// The deserializer of the single route param
+    fn single(&self) -> Result<ValueDeserializer<'a>, Error> {
+        match self.params {
+            [param] => Ok(ValueDeserializer::new(param)),
+            params => Err(de::Error::custom(format!(
+                "expected 1 route param but the route has {}",
+                params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! deserialize_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ParamsDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ParamsAccess {
+            params: self.params.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ParamsAccess {
+            params: self.params.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if len != self.params.len() {
+            return Err(de::Error::invalid_length(self.params.len(), &visitor));
+        }
+
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    /// The route has no params when its optional segment is left out, ex; `/posts/{page?}`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.params.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    deserialize_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+///
+/// Gives the params one by one, as map entries or sequence elements
+///
+struct ParamsAccess<'a> {
+    params: std::slice::Iter<'a, RequestParam>,
+
+    /// the param of the last key given to the map visitor
+    value: Option<&'a RequestParam>,
+}
+
+impl<'de> MapAccess<'de> for ParamsAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some(param) = self.params.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(param);
+        let key: StrDeserializer<Error> = param.key.as_str().into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(param) => seed.deserialize(ValueDeserializer::new(param)),
+            None => Err(de::Error::custom("route param value without a key")),
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for ParamsAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.params.next() {
+            Some(param) => seed.deserialize(ValueDeserializer::new(param)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+///
+/// Deserialize a single param value, parsing it into the requested type
+///
+struct ValueDeserializer<'a> {
+    param: &'a RequestParam,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn new(param: &'a RequestParam) -> Self {
+        Self { param }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, expected: &str) -> Result<T, Error> {
+        self.param.value.parse().map_err(|_| {
+            de::Error::custom(format!(
+                "`{}` param must be {}, got `{}`",
+                self.param.key, expected, self.param.value
+            ))
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident($expected:literal),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.parse($expected)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(&self.param.value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value: StrDeserializer<Error> = self.param.value.as_str().into_deserializer();
+        value.deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool("true or false"),
+        deserialize_i8 => visit_i8("an integer"),
+        deserialize_i16 => visit_i16("an integer"),
+        deserialize_i32 => visit_i32("an integer"),
+        deserialize_i64 => visit_i64("an integer"),
+        deserialize_u8 => visit_u8("a positive integer"),
+        deserialize_u16 => visit_u16("a positive integer"),
+        deserialize_u32 => visit_u32("a positive integer"),
+        deserialize_u64 => visit_u64("a positive integer"),
+        deserialize_f32 => visit_f32("a number"),
+        deserialize_f64 => visit_f64("a number"),
+        deserialize_char => visit_char("a single character"),
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn params(params: &[(&str, &str)]) -> Vec<RequestParam> {
+        params
+            .iter()
+            .map(|(key, value)| RequestParam {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    fn deserialize<'a, T: Deserialize<'a>>(params: &'a [RequestParam]) -> Result<T, Error> {
+        T::deserialize(ParamsDeserializer::new(params))
+    }
+
+    #[test]
+    fn single_param_is_parsed() {
+        let params = params(&[("id", "42")]);
+
+        assert_eq!(deserialize::<u32>(&params).unwrap(), 42);
+        assert_eq!(deserialize::<String>(&params).unwrap(), "42");
+    }
+
+    #[test]
+    fn invalid_single_param_error_names_the_param() {
+        let params = params(&[("id", "abc")]);
+
+        let error = deserialize::<u32>(&params).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "`id` param must be a positive integer, got `abc`"
+        );
+    }
+
+    #[test]
+    fn single_value_needs_exactly_one_param() {
+        let params = params(&[("id", "1"), ("comment_id", "2")]);
+
+        assert!(deserialize::<u32>(&params).is_err());
+    }
+
+    #[test]
+    fn optional_single_param_is_none_without_params() {
+        assert_eq!(deserialize::<Option<u32>>(&[]).unwrap(), None);
+        assert_eq!(
+            deserialize::<Option<u32>>(&params(&[("page", "2")])).unwrap(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn tuple_gets_the_params_in_order() {
+        let params = params(&[("id", "1"), ("slug", "hello")]);
+
+        assert_eq!(
+            deserialize::<(u32, String)>(&params).unwrap(),
+            (1, "hello".to_owned())
+        );
+        assert!(deserialize::<(u32,)>(&params).is_err());
+    }
+
+    #[test]
+    fn struct_gets_the_params_by_name() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Comment {
+            comment_id: u64,
+            id: u32,
+            draft: Option<bool>,
+        }
+
+        let params = params(&[("id", "1"), ("comment_id", "2")]);
+
+        assert_eq!(
+            deserialize::<Comment>(&params).unwrap(),
+            Comment {
+                comment_id: 2,
+                id: 1,
+                draft: None
+            }
+        );
+    }
+
+    #[test]
+    fn enum_variant_is_matched_by_name() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Format {
+            Json,
+            Html,
+        }
+
+        let params = params(&[("format", "json")]);
+
+        assert_eq!(deserialize::<Format>(&params).unwrap(), Format::Json);
+    }
+}
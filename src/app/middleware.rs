@@ -0,0 +1,276 @@
+use std::{future::Future, sync::Arc};
+
+use tokio::sync::Semaphore;
+
+use crate::http::{
+    request::Request,
+    response::{HttpStatusCode, Response},
+};
+
+use super::{
+    error_handlers::ErrorHandlers,
+    route::{BoxFuture, RouteHandler},
+};
+
+///
+/// Logic that runs around the route handlers (ex; auth, logging, header injection)
+///
+/// Middlewares form an onion around the handler, each one gets the request and
+/// the `Next` step of the pipeline, it can:
+/// - change the request before passing it to `next.run(request)`
+/// - change the response returned by `next.run(request)`
+/// - return its own response without calling `next` at all (short-circuit)
+///
+/// Any `Fn(Request, Next) -> impl Future<Output = Response>` closure is a middleware.
+///
+pub trait Middleware: Send + Sync + 'static {
+    fn handle<'a>(&'a self, request: Request, next: Next) -> BoxFuture<'a, Response>;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(Request, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    fn handle<'a>(&'a self, request: Request, next: Next) -> BoxFuture<'a, Response> {
+        Box::pin(self(request, next))
+    }
+}
+
+///
+/// The rest of the pipeline after the current middleware,
+/// the remaining middlewares then the route handler
+///
+pub struct Next {
+    /// the middlewares left to run, in order
+    middlewares: Vec<Arc<dyn Middleware>>,
+
+    /// the index of the next middleware to run
+    index: usize,
+
+    /// the matched route handler, None if no route matches the request
+    handler: Option<RouteHandler>,
+
+    /// permits to run sync handlers on the blocking thread pool
+    workers: Arc<Semaphore>,
+
+    /// builds the response when there is no handler, or the handler failed
+    errors: Arc<ErrorHandlers>,
+}
+
+impl Next {
+    pub(crate) fn new(
+        middlewares: Vec<Arc<dyn Middleware>>,
+        handler: Option<RouteHandler>,
+        workers: Arc<Semaphore>,
+        errors: Arc<ErrorHandlers>,
+    ) -> Self {
+        Self {
+            middlewares,
+            index: 0,
+            handler,
+            workers,
+            errors,
+        }
+    }
+
+    ///
+    /// Run the rest of the pipeline with the request and get its response
+    ///
+    /// If no route matches the request the pipeline ends with the 404 Not Found error response,
+    /// and with the 500 Internal Server Error response if the handler is cancelled.
+    ///
+    /// # Panic
+    /// this method panics if the handler panics, even if it ran on the blocking thread pool
+    ///
+    pub async fn run(mut self, request: Request) -> Response {
+        if let Some(middleware) = self.middlewares.get(self.index) {
+            let middleware = Arc::clone(middleware);
+            self.index += 1;
+
+            return middleware.handle(request, self).await;
+        }
+
+        match self.handler {
+            Some(handler) => match handler.call(request, &self.workers).await {
+                Ok(response) => response,
+
+                // the panic is caught and reported by `App::dispatch`, like the panics of the middlewares
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+
+                Err(e) => {
+                    eprintln!("Route handler failed: {}", e);
+                    self.errors.response(HttpStatusCode::ServerError)
+                }
+            },
+            None => self.errors.response(HttpStatusCode::NotFound),
+        }
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        app::App,
+        http::{http_header::HttpHeader, http_method::HttpMethod},
+    };
+
+    async fn dispatch(app: &App, raw: &str) -> Response {
+        let request = Request::initial_parse(&mut raw.as_bytes()).await.unwrap();
+        let route = app.get_route(&request.method, &request.base_path);
+
+        app.dispatch(request, route, Arc::new(Semaphore::new(1)))
+            .await
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    /// A middleware that records its name before and after the rest of the pipeline
+    fn trace(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> impl Middleware {
+        let log = Arc::clone(log);
+
+        move |request: Request, next: Next| {
+            let log = Arc::clone(&log);
+            async move {
+                log.lock().unwrap().push(format!("{} before", name));
+                let response = next.run(request).await;
+                log.lock().unwrap().push(format!("{} after", name));
+                response
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn middlewares_run_as_an_onion() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let handler_log = Arc::clone(&log);
+        let app = App::default()
+            .get("/", move |_r: Request| {
+                handler_log.lock().unwrap().push("handler".to_owned());
+                Response::ok("home")
+            })
+            .wrap(trace("outer", &log))
+            .wrap(trace("inner", &log));
+
+        let response = dispatch(&app, "GET / HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "home");
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "outer before",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware_can_short_circuit() {
+        let app = App::default()
+            .get("/", |_r: Request| -> Response {
+                panic!("must not be called")
+            })
+            .wrap(|request: Request, next: Next| async move {
+                if request.header("Authorization").is_none() {
+                    return Response::unauthorized();
+                }
+                next.run(request).await
+            });
+
+        let response = dispatch(&app, "GET / HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn middleware_can_change_the_request_and_the_response() {
+        let app = App::default()
+            .get("/", |r: Request| {
+                Response::ok(r.header("X-User").unwrap_or(""))
+            })
+            .wrap(|mut request: Request, next: Next| async move {
+                request.headers.push(HttpHeader {
+                    key: "X-User".to_owned(),
+                    value: "admin".to_owned(),
+                });
+
+                let mut response = next.run(request).await;
+                response.set_header("X-Powered-By", "rs-server");
+                response
+            });
+
+        let response = dispatch(&app, "GET / HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "admin");
+        assert_eq!(response.get_header("X-Powered-By"), Some("rs-server"));
+    }
+
+    #[tokio::test]
+    async fn app_middlewares_wrap_unmatched_requests() {
+        let app = App::default().wrap(|request: Request, next: Next| async move {
+            let mut response = next.run(request).await;
+            response.set_header("X-Seen", "yes");
+            response
+        });
+
+        let response = dispatch(&app, "GET /missing HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(response.get_header("X-Seen"), Some("yes"));
+    }
+
+    #[tokio::test]
+    async fn route_middlewares_only_wrap_their_route() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let app = App::default()
+            .get("/a", |_r: Request| Response::ok("a"))
+            .get("/b", |_r: Request| Response::ok("b"))
+            .wrap_route(HttpMethod::Get, "/a", trace("route", &log))
+            .wrap(trace("app", &log));
+
+        dispatch(&app, "GET /b HTTP/1.1\r\n").await;
+        assert_eq!(*log.lock().unwrap(), vec!["app before", "app after"]);
+
+        log.lock().unwrap().clear();
+        dispatch(&app, "GET /a HTTP/1.1\r\n").await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["app before", "route before", "route after", "app after"]
+        );
+    }
+
+    #[tokio::test]
+    async fn prefix_middlewares_only_wrap_requests_under_the_prefix() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let app = App::default()
+            .get("/admin/users", |_r: Request| Response::ok("users"))
+            .get("/administrators", |_r: Request| Response::ok("admins"))
+            .wrap_prefix("/admin", trace("admin", &log));
+
+        dispatch(&app, "GET /administrators HTTP/1.1\r\n").await;
+        assert!(log.lock().unwrap().is_empty());
+
+        dispatch(&app, "GET /admin/users HTTP/1.1\r\n").await;
+        assert_eq!(*log.lock().unwrap(), vec!["admin before", "admin after"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrapping_an_unregistered_route_panics() {
+        let _ = App::default().wrap_route(
+            HttpMethod::Get,
+            "/missing",
+            |request: Request, next: Next| async move { next.run(request).await },
+        );
+    }
+}
@@ -1,33 +1,332 @@
 #![allow(dead_code)]
 
-use std::{collections::HashMap, sync::Arc};
+use std::{panic::AssertUnwindSafe, path::PathBuf, sync::Arc};
 
-use crate::http::{http_method::HttpMethod, request::Request, response::Response};
+use futures_util::FutureExt;
 
-use self::route::Route;
+use tokio::sync::Semaphore;
 
+use crate::http::{
+    http_method::HttpMethod,
+    request::Request,
+    response::{HttpStatusCode, Response},
+};
+
+use self::{
+    error_handlers::{ErrorHandlers, PanicReport},
+    middleware::{Middleware, Next},
+    route::{IntoRouteHandler, Route, RouteHandler},
+    router::Router,
+    session::{store::SessionStore, SessionConfig, SessionMiddleware},
+    static_dir::StaticDir,
+};
+
+pub mod error_handlers;
+pub mod extract;
+pub mod middleware;
 pub mod route;
+pub mod router;
+pub mod session;
+pub mod static_dir;
 
 pub struct App {
-    /// the list of registered routes
-    routes: HashMap<HttpMethod, Vec<Route>>,
+    /// the registered routes
+    router: Router,
+
+    /// the list of directories served under a URL prefix
+    static_dirs: Vec<Arc<StaticDir>>,
+
+    /// the Cache-Control policies as (URL prefix, value), the most specific prefix first
+    cache_policies: Vec<(String, String)>,
+
+    /// the middlewares that wrap every request, the first registered is the outermost
+    middlewares: Vec<Arc<dyn Middleware>>,
+
+    /// the middlewares that only wrap requests under a URL prefix as (URL prefix, middleware)
+    scoped_middlewares: Vec<(String, Arc<dyn Middleware>)>,
+
+    /// the handlers of the requests that do not match any route as (URL prefix, handler),
+    /// the most specific prefix first
+    fallbacks: Vec<(String, RouteHandler)>,
+
+    /// builds the responses of the requests no route handler answered
+    errors: Arc<ErrorHandlers>,
 }
 
 impl App {
     fn new() -> Self {
         Self {
-            routes: HashMap::new(),
+            router: Router::default(),
+            static_dirs: vec![],
+            cache_policies: vec![],
+            middlewares: vec![],
+            scoped_middlewares: vec![],
+            fallbacks: vec![],
+            errors: Arc::default(),
         }
     }
 
+    /// Register a group of routes under a URL prefix
+    ///
+    /// ex; `App::default().group("/api/v1", |g| g.get("/users", handler))` registers `GET /api/v1/users`.
+    /// The group is built as a separate `App` then mounted, see `App::mount`.
+    ///
+    /// # Panic
+    /// this method will panic if any of the group routes is already registered
+    ///
+    pub fn group<F>(self, prefix: &str, build: F) -> Self
+    where
+        F: FnOnce(App) -> App,
+    {
+        self.mount(prefix, build(App::default()))
+    }
+
+    /// Mount another app under a URL prefix
+    ///
+    /// ex; `App::default().mount("/admin", admin::app())` serves the `GET /users` route of the admin app
+    /// on `GET /admin/users`, a `/` route is served on the prefix itself.
+    /// - the mounted app middlewares wrap every request under the prefix, after this app middlewares
+    /// - the mounted app fallback handles the unmatched requests under the prefix
+    /// - the mounted app static directories and Cache-Control policies are moved under the prefix
+    /// - the mounted app error handlers are not used, the error responses are configured on the top app
+    ///
+    /// # Panic
+    /// this method will panic if any of the mounted routes or static prefixes is already registered
+    ///
+    pub fn mount(mut self, prefix: &str, app: App) -> Self {
+        let prefix = normalize_prefix(prefix);
+
+        for route in app.router.into_routes() {
+            let path = join_path(&prefix, &route.path);
+            self.add_route(Route { path, ..route });
+        }
+
+        for static_dir in app.static_dirs {
+            self.add_static_dir(StaticDir {
+                prefix: format!("{}{}", prefix, static_dir.prefix),
+                root: static_dir.root.clone(),
+            });
+        }
+
+        for (scope, value) in app.cache_policies {
+            self = self.cache_control(&format!("{}{}", prefix, scope), &value);
+        }
+
+        for middleware in app.middlewares {
+            self.scoped_middlewares.push((prefix.clone(), middleware));
+        }
+        for (scope, middleware) in app.scoped_middlewares {
+            self.scoped_middlewares
+                .push((format!("{}{}", prefix, scope), middleware));
+        }
+        self.scoped_middlewares
+            .sort_by_key(|(prefix, _)| prefix.len());
+
+        for (scope, handler) in app.fallbacks {
+            self.add_fallback(format!("{}{}", prefix, scope), handler);
+        }
+        self
+    }
+
+    /// Handle the requests that do not match any route
+    ///
+    /// Without a fallback these requests get a 404 Not Found response.
+    /// The handler can be a sync `Fn(Request) -> Response` closure or an `async fn(Request) -> Response`.
+    ///
+    pub fn fallback<H, K>(mut self, handler: H) -> Self
+    where
+        H: IntoRouteHandler<K>,
+    {
+        self.add_fallback(String::new(), handler.into_route_handler());
+        self
+    }
+
+    /// Customize the error response the server sends for a status code
+    ///
+    /// The handler gets the default response and returns the one to send, ex;
+    /// `App::default().on_error(HttpStatusCode::NotFound, |response: Response| response.with_body("Not here"))`.
+    /// It's used for the responses the server builds itself:
+    /// - 400, 413.. when the request can not be parsed
+    /// - 404 when no route matches the request and there is no fallback
+    /// - 405 when the path only has routes for other methods
+    /// - 500 when the route handler fails
+    ///
+    /// Responses returned by route handlers are never changed.
+    ///
+    pub fn on_error<H>(mut self, status_code: HttpStatusCode, handler: H) -> Self
+    where
+        H: Fn(Response) -> Response + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.errors).set_handler(status_code, Arc::new(handler));
+        self
+    }
+
+    /// Get every panic caught while handling a request, ex; to report it to a monitoring service
+    ///
+    /// Panics in route handlers and middlewares are always caught, logged with the request route
+    /// and answered with the 500 error response, the hook is called after the panic is logged.
+    ///
+    pub fn on_panic<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&PanicReport) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.errors).set_panic_hook(Arc::new(hook));
+        self
+    }
+
+    /// Send a page as the body of the 404 Not Found error responses
+    ///
+    /// ex; `App::default().not_found_page("static/404.html")`, the page is read on every response
+    /// so it can be changed without restarting the server, an empty body is sent if it can not be read.
+    ///
+    pub fn not_found_page(mut self, path: &str) -> Self {
+        Arc::make_mut(&mut self.errors).set_not_found_page(PathBuf::from(path));
+        self
+    }
+
+    /// Build the error response the server sends for a status code, see `App::on_error`
+    pub fn error_response(&self, status_code: HttpStatusCode) -> Response {
+        self.errors.response(status_code)
+    }
+
+    fn add_fallback(&mut self, prefix: String, handler: RouteHandler) {
+        self.fallbacks
+            .retain(|(registered, _)| registered != &prefix);
+        self.fallbacks.push((prefix, handler));
+        self.fallbacks
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    }
+
+    /// Get the handler of the unmatched requests to a path, the most specific fallback wins
+    fn get_fallback(&self, path: &str) -> Option<RouteHandler> {
+        self.fallbacks
+            .iter()
+            .find(|(prefix, _)| strip_path_prefix(prefix, path).is_some())
+            .map(|(_, handler)| handler.clone())
+    }
+
+    /// Wrap every request with a middleware
+    ///
+    /// Middlewares run in the order they are registered, the first one is the outermost layer,
+    /// they also run for requests that do not match any route.
+    /// ex; `App::default().wrap(|request: Request, next: Next| async move { next.run(request).await })`
+    ///
+    pub fn wrap<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Wrap the requests under a URL prefix with a middleware
+    ///
+    /// ex; `App::default().wrap_prefix("/admin", auth)` runs `auth` for `/admin` and `/admin/users`
+    /// but not for `/administrators`. Prefix middlewares run after the app middlewares,
+    /// the least specific prefix first.
+    ///
+    pub fn wrap_prefix<M: Middleware>(mut self, prefix: &str, middleware: M) -> Self {
+        self.scoped_middlewares
+            .push((normalize_prefix(prefix), Arc::new(middleware)));
+        self.scoped_middlewares
+            .sort_by_key(|(prefix, _)| prefix.len());
+        self
+    }
+
+    /// Wrap a single registered route with a middleware
+    ///
+    /// Route middlewares run after the app and prefix middlewares, the closest to the handler.
+    ///
+    /// # Panic
+    /// this method will panic if the route is not registered
+    ///
+    pub fn wrap_route<M: Middleware>(
+        mut self,
+        method: HttpMethod,
+        path: &str,
+        middleware: M,
+    ) -> Self {
+        match self.router.get_mut(&method, path) {
+            Some(route) => route.middlewares.push(Arc::new(middleware)),
+            None => panic!("this `{:?} {}` path is not registered!", method, path),
+        }
+        self
+    }
+
+    /// Give every request a session kept in `store`, see `Session`
+    ///
+    /// ex; `App::default().sessions(MemorySessionStore::default(), SessionConfig::default())`.
+    /// The sessions are loaded by a middleware registered like `wrap`, so only the middlewares
+    /// registered after this call can use them.
+    ///
+    pub fn sessions<S: SessionStore>(self, store: S, config: SessionConfig) -> Self {
+        self.wrap(SessionMiddleware::new(store, config))
+    }
+
+    /// Set the Cache-Control header of the successful responses to requests under a URL prefix
+    ///
+    /// ex; `App::default().cache_control("/assets", "public, max-age=86400")`.
+    /// The most specific prefix wins, and a Cache-Control header set by the handler is never replaced.
+    ///
+    pub fn cache_control(mut self, prefix: &str, value: &str) -> Self {
+        let prefix = normalize_prefix(prefix);
+
+        self.cache_policies
+            .retain(|(registered, _)| registered != &prefix);
+        self.cache_policies.push((prefix, value.to_owned()));
+        self.cache_policies
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Get the Cache-Control policy configured for a request path, if any
+    pub fn get_cache_control(&self, path: &str) -> Option<&str> {
+        self.cache_policies
+            .iter()
+            .find(|(prefix, _)| strip_path_prefix(prefix, path).is_some())
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Serve the files of a directory under a URL prefix
+    ///
+    /// ex; `App::default().static_dir("/assets", "static/")` serves `static/logo.png` on `GET /assets/logo.png`.
+    /// Registered routes take precedence over static files, see `StaticDir::serve` for how files are looked up.
+    ///
+    /// # Panic
+    /// this method will panic if the prefix is already registered
+    ///
+    pub fn static_dir(mut self, prefix: &str, dir: &str) -> Self {
+        self.add_static_dir(StaticDir::new(prefix, dir));
+        self
+    }
+
+    fn add_static_dir(&mut self, static_dir: StaticDir) {
+        if self
+            .static_dirs
+            .iter()
+            .any(|registered| registered.prefix == static_dir.prefix)
+        {
+            panic!(
+                "this `{}` static prefix is already registered!",
+                static_dir.prefix
+            );
+        }
+
+        self.static_dirs.push(Arc::new(static_dir));
+
+        // the most specific prefix is checked first
+        self.static_dirs
+            .sort_by_key(|static_dir| std::cmp::Reverse(static_dir.prefix.len()));
+    }
+
     /// Register an HTTP GET route handler
     ///
+    /// The handler can be a sync `Fn(Request) -> Response` closure or an `async fn(Request) -> Response`,
+    /// sync handlers are executed on the blocking thread pool.
+    ///
     /// # Panic
-    /// this method will panic if the path is already registered
+    /// this method will panic if the path is already registered, or is ambiguous with a registered path
     ///
-    pub fn get<F>(mut self, path: &str, handler: F) -> Self
+    pub fn get<H, K>(mut self, path: &str, handler: H) -> Self
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        H: IntoRouteHandler<K>,
     {
         self.register_route(HttpMethod::Get, path, handler);
         self
@@ -36,11 +335,11 @@ impl App {
     /// Register an HTTP POST route handler
     ///
     /// # Panic
-    /// this method will panic if the path is already registered
+    /// this method will panic if the path is already registered, or is ambiguous with a registered path
     ///
-    pub fn post<F>(mut self, path: &str, handler: F) -> Self
+    pub fn post<H, K>(mut self, path: &str, handler: H) -> Self
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        H: IntoRouteHandler<K>,
     {
         self.register_route(HttpMethod::Post, path, handler);
         self
@@ -49,11 +348,11 @@ impl App {
     /// Register an HTTP PUT route handler
     ///
     /// # Panic
-    /// this method will panic if the path is already registered
+    /// this method will panic if the path is already registered, or is ambiguous with a registered path
     ///
-    pub fn put<F>(mut self, path: &str, handler: F) -> Self
+    pub fn put<H, K>(mut self, path: &str, handler: H) -> Self
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        H: IntoRouteHandler<K>,
     {
         self.register_route(HttpMethod::Put, path, handler);
         self
@@ -62,70 +361,228 @@ impl App {
     /// Register an HTTP DELETE route handler
     ///
     /// # Panic
-    /// this method will panic if the path is already registered
+    /// this method will panic if the path is already registered, or is ambiguous with a registered path
     ///
-    pub fn delete<F>(mut self, path: &str, handler: F) -> Self
+    pub fn delete<H, K>(mut self, path: &str, handler: H) -> Self
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        H: IntoRouteHandler<K>,
     {
         self.register_route(HttpMethod::Delete, path, handler);
         self
     }
 
-    fn register_route<F>(&mut self, method: HttpMethod, path: &str, handler: F)
+    /// Register an HTTP PATCH route handler
+    ///
+    /// # Panic
+    /// this method will panic if the path is already registered, or is ambiguous with a registered path
+    ///
+    pub fn patch<H, K>(mut self, path: &str, handler: H) -> Self
+    where
+        H: IntoRouteHandler<K>,
+    {
+        self.register_route(HttpMethod::Patch, path, handler);
+        self
+    }
+
+    /// Register the same route handler for multiple HTTP methods
+    ///
+    /// ex; `App::default().route(&[HttpMethod::Get, HttpMethod::Other("PROPFIND".to_owned())], "/files", handler)`,
+    /// this is also how extension methods are registered.
+    ///
+    /// # Panic
+    /// this method will panic if the path is already registered for any of the methods,
+    /// or is ambiguous with a registered path
+    ///
+    pub fn route<H, K>(mut self, methods: &[HttpMethod], path: &str, handler: H) -> Self
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        H: IntoRouteHandler<K>,
     {
-        let method_routes = self.routes.entry(method).or_insert(Vec::new());
+        let handler = handler.into_route_handler();
 
-        let path_exist = method_routes.iter().any(|route| route.path == path);
-        if path_exist {
-            panic!("this `{:?} {}` path is already registered!", method, path);
+        for method in methods {
+            self.add_route(Route::new(method.clone(), path.to_owned(), handler.clone()));
         }
+        self
+    }
+
+    /// Register a route handler for all the standard HTTP methods
+    ///
+    /// Extension methods (ex; `PROPFIND`) are not included, they have to be registered using `App::route`.
+    ///
+    /// # Panic
+    /// this method will panic if the path is already registered for any of the methods,
+    /// or is ambiguous with a registered path
+    ///
+    pub fn any<H, K>(self, path: &str, handler: H) -> Self
+    where
+        H: IntoRouteHandler<K>,
+    {
+        self.route(&HttpMethod::STANDARD, path, handler)
+    }
 
-        // register the route
-        method_routes.push(Route::new(method, path.to_owned(), Arc::new(handler)));
+    fn register_route<H, K>(&mut self, method: HttpMethod, path: &str, handler: H)
+    where
+        H: IntoRouteHandler<K>,
+    {
+        self.add_route(Route::new(
+            method,
+            path.to_owned(),
+            handler.into_route_handler(),
+        ));
     }
 
-    pub fn get_route(&mut self, method: HttpMethod, path: &String) -> Option<Route> {
-        let method_routes = self.routes.entry(method).or_insert(Vec::new());
+    fn add_route(&mut self, route: Route) {
+        self.router.insert(route);
+    }
 
-        method_routes
-            .iter()
-            .find(|route| {
-                // if the two paths do not have the same number of route segments
-                // this means they do not match
-                if route.path.split('/').count() != path.split('/').count() {
-                    return false;
-                }
+    ///
+    /// Run the request through the middlewares that wrap it then the matched route handler
+    ///
+    /// If no route matches the request:
+    /// - OPTIONS requests get the methods allowed for the path in the `Allow` header
+    /// - requests to a path that has routes for other methods get a 405 Method Not Allowed response
+    /// - other requests are handled by the fallback, or get a 404 Not Found response
+    ///
+    /// If a middleware or the handler panics the panic is reported (see `App::on_panic`)
+    /// and the request gets the 500 error response.
+    ///
+    pub async fn dispatch(
+        &self,
+        request: Request,
+        route: Option<Route>,
+        workers: Arc<Semaphore>,
+    ) -> Response {
+        let mut middlewares = self.middlewares.clone();
+        middlewares.extend(
+            self.scoped_middlewares
+                .iter()
+                .filter(|(prefix, _)| strip_path_prefix(prefix, &request.base_path).is_some())
+                .map(|(_, middleware)| Arc::clone(middleware)),
+        );
 
-                // get the route params as (index, name)
-                let route_params = route.get_params();
-                if route_params.is_empty() {
-                    // no params defined so we jsut compare the two paths as strings
-                    &route.path == path
-                } else {
-                    // here we know both paths have the same number of segments
-                    // and we know the names and positions of the defined params,
-                    // so the paths will be considered a match if they have the same non-param sluts
-
-                    let param_indexs: Vec<_> = route_params.iter().map(|param| param.0).collect();
-
-                    route
-                        .path
-                        .split('/')
-                        .zip(path.split('/'))
-                        .enumerate()
-                        .all(|(i, (f, s))| {
-                            if !param_indexs.contains(&i) {
-                                return f == s;
-                            }
-
-                            true
-                        })
+        let method = request.method.clone();
+        let path = request.base_path.clone();
+        let route_path = route.as_ref().map(|route| route.path.clone());
+
+        let handler = match route {
+            Some(route) => {
+                middlewares.extend(route.middlewares);
+                Some(route.handler)
+            }
+            None => match self.allowed_methods(&request.base_path) {
+                allowed if allowed.is_empty() => self.get_fallback(&request.base_path),
+                allowed if request.method == HttpMethod::Options => {
+                    Some(self.allow_handler(HttpStatusCode::NoContent, allowed))
                 }
-            })
-            .map(|route| route.to_owned())
+                allowed => Some(self.allow_handler(HttpStatusCode::MethodNotAllowed, allowed)),
+            },
+        };
+
+        let next = Next::new(middlewares, handler, workers, Arc::clone(&self.errors));
+        match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+            Ok(response) => response,
+            Err(payload) => {
+                let report = PanicReport::new(method, path, route_path, payload.as_ref());
+                self.errors.report_panic(&report);
+
+                self.error_response(HttpStatusCode::ServerError)
+            }
+        }
+    }
+
+    ///
+    /// Build a handler answering with the allowed methods in the `Allow` header
+    ///
+    fn allow_handler(&self, status_code: HttpStatusCode, methods: Vec<HttpMethod>) -> RouteHandler {
+        let allow = methods
+            .iter()
+            .map(|method| method.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let errors = Arc::clone(&self.errors);
+        let handler = move |_request: Request| {
+            let mut response = errors.response(status_code.clone());
+            response.set_header("Allow", &allow);
+            async move { response }
+        };
+
+        handler.into_route_handler()
+    }
+
+    ///
+    /// Find the route that matches a request, registered routes have priority over static directories
+    ///
+    /// HEAD requests are handled by the GET route of the path if they do not have their own route,
+    /// the response body is not sent.
+    ///
+    pub fn get_route(&self, method: &HttpMethod, path: &str) -> Option<Route> {
+        // `*` (`OPTIONS *`) and `host:port` (`CONNECT`) targets never match a route
+        if !path.starts_with('/') {
+            return None;
+        }
+
+        let route = self
+            .router
+            .find(method, path)
+            .or_else(|| self.find_static_route(method, path));
+
+        match route {
+            None if *method == HttpMethod::Head => self.get_route(&HttpMethod::Get, path),
+            route => route,
+        }
+    }
+
+    ///
+    /// Get the methods allowed for a request path, it's empty if no route matches the path
+    ///
+    /// `HEAD` is allowed if `GET` is, and `OPTIONS` is always allowed for known paths.
+    /// `*` (`OPTIONS *` request) gets the methods of all the registered routes.
+    ///
+    pub fn allowed_methods(&self, path: &str) -> Vec<HttpMethod> {
+        let mut methods = if path == "*" {
+            self.router.all_methods()
+        } else {
+            self.router.methods(path)
+        };
+
+        if self.find_static_route(&HttpMethod::Get, path).is_some() {
+            methods.push(HttpMethod::Get);
+        }
+        if methods.is_empty() {
+            return methods;
+        }
+        if methods.contains(&HttpMethod::Get) {
+            methods.push(HttpMethod::Head);
+        }
+        methods.push(HttpMethod::Options);
+
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+
+    ///
+    /// Build a route that serves the static directory the path is under, if any
+    ///
+    fn find_static_route(&self, method: &HttpMethod, path: &str) -> Option<Route> {
+        if *method != HttpMethod::Get && *method != HttpMethod::Head {
+            return None;
+        }
+
+        let static_dir = self
+            .static_dirs
+            .iter()
+            .find(|static_dir| static_dir.strip_prefix(path).is_some())?;
+
+        let static_dir = Arc::clone(static_dir);
+        let handler = move |request: Request| static_dir.serve(&request);
+
+        Some(Route::new(
+            method.clone(),
+            path.to_owned(),
+            handler.into_route_handler(),
+        ))
     }
 }
 
@@ -134,3 +591,423 @@ impl Default for App {
         Self::new()
     }
 }
+
+///
+/// Normalize a URL prefix to start with a slash and have no trailing slash, `/` becomes empty
+///
+pub(crate) fn normalize_prefix(prefix: &str) -> String {
+    format!("/{}", prefix.trim_matches('/'))
+        .trim_end_matches('/')
+        .to_owned()
+}
+
+///
+/// Join a normalized prefix and a route path, the `/` route path is the prefix itself
+///
+fn join_path(prefix: &str, path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" if prefix.is_empty() => "/".to_owned(),
+        "" => prefix.to_owned(),
+        _ => format!("{}/{}", prefix, path.trim_start_matches('/')),
+    }
+}
+
+///
+/// Get the rest of the path after a normalized prefix
+///
+/// It returns None if the path is not under the prefix, the prefix has to match whole segments
+/// so `/assets` does not match `/assetsfoo`.
+///
+pub(crate) fn strip_path_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    async fn dispatch(app: &App, raw: &str) -> Response {
+        let mut request = Request::initial_parse(&mut raw.as_bytes()).await.unwrap();
+        let route = app.get_route(&request.method, &request.base_path);
+        request
+            .complete_parsing(&mut "\r\n".as_bytes(), route.as_ref(), 1024)
+            .await
+            .unwrap();
+
+        app.dispatch(request, route, Arc::new(Semaphore::new(1)))
+            .await
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    fn users() -> App {
+        App::default()
+            .get("/", |_r: Request| Response::ok("users"))
+            .get("/{id}", |r: Request| {
+                Response::ok(&r.get_route_param::<String>("id").unwrap())
+            })
+    }
+
+    #[test]
+    fn join_path_works() {
+        assert_eq!(join_path("/api", "/users"), "/api/users");
+        assert_eq!(join_path("/api", "users"), "/api/users");
+        assert_eq!(join_path("/api", "/"), "/api");
+        assert_eq!(join_path("", "/"), "/");
+    }
+
+    #[tokio::test]
+    async fn group_routes_are_served_under_the_prefix() {
+        let app = App::default().group("/api/v1", |g| {
+            g.get("/users", |_r: Request| Response::ok("users"))
+        });
+
+        assert!(app.get_route(&HttpMethod::Get, "/users").is_none());
+
+        let response = dispatch(&app, "GET /api/v1/users HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "users");
+    }
+
+    #[tokio::test]
+    async fn mounted_app_routes_keep_their_params() {
+        let app = App::default().mount("/users", users());
+
+        let response = dispatch(&app, "GET /users HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "users");
+
+        let response = dispatch(&app, "GET /users/42 HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "42");
+    }
+
+    #[tokio::test]
+    async fn nested_groups_are_resolved() {
+        let app = App::default().group("/api", |api| api.mount("/v1/users", users()));
+
+        let response = dispatch(&app, "GET /api/v1/users/7 HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "7");
+    }
+
+    #[tokio::test]
+    async fn mounted_app_middlewares_only_wrap_its_prefix() {
+        let admin = App::default()
+            .get("/users", |_r: Request| Response::ok("admin users"))
+            .wrap(|request: Request, next: Next| async move {
+                if request.header("Authorization").is_none() {
+                    return Response::unauthorized();
+                }
+                next.run(request).await
+            });
+        let app = App::default()
+            .get("/users", |_r: Request| Response::ok("users"))
+            .mount("/admin", admin);
+
+        let response = dispatch(&app, "GET /users HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+
+        let response = dispatch(&app, "GET /admin/users HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::Unauthorized);
+
+        let response = dispatch(&app, "GET /admin/missing HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn the_most_specific_fallback_handles_unmatched_requests() {
+        let api = App::default().fallback(|_r: Request| Response::ok("api fallback"));
+        let app = App::default()
+            .fallback(|_r: Request| async { Response::ok("app fallback") })
+            .mount("/api", api);
+
+        let response = dispatch(&app, "GET /api/missing HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "api fallback");
+
+        let response = dispatch(&app, "GET /missing HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "app fallback");
+    }
+
+    #[tokio::test]
+    async fn unmatched_requests_without_fallback_are_not_found() {
+        let app = App::default().mount("/users", users());
+
+        let response = dispatch(&app, "GET /posts HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+    }
+
+    #[test]
+    fn mounted_static_dirs_and_cache_policies_are_prefixed() {
+        let assets = App::default()
+            .static_dir("/", "static/")
+            .cache_control("/", "max-age=60");
+        let app = App::default().mount("/assets", assets);
+
+        assert!(app
+            .get_route(&HttpMethod::Get, "/assets/index.html")
+            .is_some());
+        assert!(app.get_route(&HttpMethod::Get, "/index.html").is_none());
+        assert_eq!(app.get_cache_control("/assets/a.css"), Some("max-age=60"));
+        assert_eq!(app.get_cache_control("/a.css"), None);
+    }
+
+    fn methods_app() -> App {
+        App::default()
+            .get("/users/{id}", |_r: Request| Response::ok("user"))
+            .delete("/users/{id}", |_r: Request| Response::ok("deleted"))
+            .post("/users", |_r: Request| Response::ok("created"))
+    }
+
+    #[tokio::test]
+    async fn other_method_of_a_known_path_is_not_allowed() {
+        let response = dispatch(&methods_app(), "PUT /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::MethodNotAllowed);
+        assert_eq!(
+            response.get_header("Allow"),
+            Some("GET, HEAD, DELETE, OPTIONS")
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_still_not_found() {
+        let response = dispatch(&methods_app(), "PUT /posts/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn head_request_runs_the_get_handler() {
+        let response = dispatch(&methods_app(), "HEAD /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "user");
+    }
+
+    #[tokio::test]
+    async fn options_request_gets_the_allowed_methods() {
+        let response = dispatch(&methods_app(), "OPTIONS /users HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NoContent);
+        assert_eq!(response.get_header("Allow"), Some("POST, OPTIONS"));
+
+        let response = dispatch(&methods_app(), "OPTIONS * HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NoContent);
+        assert_eq!(
+            response.get_header("Allow"),
+            Some("GET, HEAD, POST, DELETE, OPTIONS")
+        );
+    }
+
+    #[tokio::test]
+    async fn registered_options_route_is_used() {
+        let mut app = methods_app();
+        app.register_route(HttpMethod::Options, "/users", |_r: Request| {
+            Response::ok("custom")
+        });
+
+        let response = dispatch(&app, "OPTIONS /users HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "custom");
+    }
+
+    #[tokio::test]
+    async fn patch_route_is_registered() {
+        let app = App::default().patch("/users/{id}", |_r: Request| Response::ok("patched"));
+
+        let response = dispatch(&app, "PATCH /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(body(&response), "patched");
+    }
+
+    #[tokio::test]
+    async fn route_is_registered_for_all_its_methods() {
+        let app = App::default().route(
+            &[HttpMethod::Put, HttpMethod::Other("PROPFIND".to_owned())],
+            "/files/{*path}",
+            |r: Request| Response::ok(r.method.as_str()),
+        );
+
+        let response = dispatch(&app, "PROPFIND /files/a.txt HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "PROPFIND");
+
+        let response = dispatch(&app, "PUT /files/a.txt HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "PUT");
+
+        let response = dispatch(&app, "GET /files/a.txt HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::MethodNotAllowed);
+        assert_eq!(response.get_header("Allow"), Some("PUT, OPTIONS, PROPFIND"));
+    }
+
+    #[tokio::test]
+    async fn any_route_handles_all_standard_methods() {
+        let app = App::default().any("/echo", |r: Request| async move {
+            Response::ok(r.method.as_str())
+        });
+
+        for method in ["GET", "POST", "DELETE", "OPTIONS", "TRACE"] {
+            let response = dispatch(&app, &format!("{} /echo HTTP/1.1\r\n", method)).await;
+            assert_eq!(body(&response), method);
+        }
+
+        let response = dispatch(&app, "PROPFIND /echo HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::MethodNotAllowed);
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn route_panics_if_one_of_its_methods_is_registered() {
+        let _ = App::default()
+            .post("/users", |_r: Request| Response::ok("created"))
+            .route(
+                &[HttpMethod::Get, HttpMethod::Post],
+                "/users",
+                |_r: Request| Response::ok("users"),
+            );
+    }
+
+    #[tokio::test]
+    async fn error_handlers_build_not_found_and_not_allowed_responses() {
+        let app = methods_app()
+            .on_error(HttpStatusCode::NotFound, |response: Response| {
+                response.with_body("nothing here")
+            })
+            .on_error(HttpStatusCode::MethodNotAllowed, |response: Response| {
+                response.with_body("not allowed")
+            });
+
+        let response = dispatch(&app, "GET /posts HTTP/1.1\r\n").await;
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(body(&response), "nothing here");
+
+        let response = dispatch(&app, "PUT /users/1 HTTP/1.1\r\n").await;
+        assert_eq!(body(&response), "not allowed");
+        assert_eq!(
+            response.get_header("Allow"),
+            Some("GET, HEAD, DELETE, OPTIONS")
+        );
+    }
+
+    #[tokio::test]
+    async fn error_handlers_do_not_change_handler_responses() {
+        let app = App::default()
+            .get("/users/{id}", |_r: Request| Response::not_found())
+            .on_error(HttpStatusCode::NotFound, |response: Response| {
+                response.with_body("nothing here")
+            });
+
+        let response = dispatch(&app, "GET /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(body(&response), "");
+    }
+
+    #[tokio::test]
+    async fn failed_handler_gets_the_500_error_response() {
+        let app = App::default()
+            .get("/", |_r: Request| -> Response { panic!("handler failed") })
+            .on_error(HttpStatusCode::ServerError, |response: Response| {
+                response.with_body("try again later")
+            });
+
+        let response = dispatch(&app, "GET / HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::ServerError);
+        assert_eq!(body(&response), "try again later");
+    }
+
+    #[tokio::test]
+    async fn async_handler_panic_gets_the_500_error_response() {
+        let app = App::default().get("/users/{id}", |_r: Request| async {
+            if true {
+                panic!("user not found");
+            }
+            Response::ok("user")
+        });
+
+        let response = dispatch(&app, "GET /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::ServerError);
+    }
+
+    #[tokio::test]
+    async fn middleware_panic_gets_the_500_error_response() {
+        let app = App::default()
+            .get("/", |_r: Request| Response::ok("home"))
+            .wrap(|_request: Request, _next: Next| async { panic!("middleware failed") });
+
+        let response = dispatch(&app, "GET / HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::ServerError);
+    }
+
+    #[tokio::test]
+    async fn panics_are_reported_to_the_hook() {
+        let reports = Arc::new(std::sync::Mutex::new(vec![]));
+        let hook_reports = Arc::clone(&reports);
+        let app = App::default()
+            .get("/users/{id}", |_r: Request| -> Response {
+                panic!("user {} not found", 1)
+            })
+            .get("/posts", |_r: Request| async { Response::ok("posts") })
+            .on_panic(move |report: &PanicReport| {
+                hook_reports.lock().unwrap().push(report.clone());
+            });
+
+        dispatch(&app, "GET /posts HTTP/1.1\r\n").await;
+        dispatch(&app, "GET /users/1 HTTP/1.1\r\n").await;
+
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![PanicReport {
+                method: HttpMethod::Get,
+                path: "/users/1".to_owned(),
+                route: Some("/users/{id}".to_owned()),
+                message: "user 1 not found".to_owned(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn not_found_page_is_sent_for_unmatched_requests() {
+        let app = App::default().not_found_page("static/404.html");
+
+        let response = dispatch(&app, "GET /missing HTTP/1.1\r\n").await;
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn static_dirs_allow_get_and_head() {
+        let app = App::default().static_dir("/assets", "static/");
+
+        assert_eq!(
+            app.allowed_methods("/assets/index.html"),
+            vec![HttpMethod::Get, HttpMethod::Head, HttpMethod::Options]
+        );
+        assert!(app
+            .get_route(&HttpMethod::Head, "/assets/index.html")
+            .is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn mounting_a_registered_route_panics() {
+        let _ = App::default()
+            .get("/users", |_r: Request| Response::ok("users"))
+            .mount("/users", users());
+    }
+}
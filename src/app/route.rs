@@ -1,14 +1,159 @@
-use std::sync::Arc;
+use std::{future::Future, pin::Pin, sync::Arc};
 
-use crate::http::{http_method::HttpMethod, request::Request, response::Response};
+use tokio::{sync::Semaphore, task::JoinError};
 
-pub type RouteHandler = Arc<dyn Fn(Request) -> Response + Send + Sync + 'static>;
+use crate::http::{
+    http_method::HttpMethod, request::Request, request_param::RequestParam, response::Response,
+};
+
+use super::{
+    extract::{ExtractionError, FromRequest},
+    middleware::Middleware,
+    router::Segment,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Clone)]
+pub enum RouteHandler {
+    /// A blocking handler, it runs on the blocking thread pool so it does not block the executor
+    Sync(Arc<dyn Fn(Request) -> Response + Send + Sync + 'static>),
+
+    /// An async handler, it runs on the connection task
+    Async(Arc<dyn Fn(Request) -> BoxFuture<'static, Response> + Send + Sync + 'static>),
+}
+
+impl RouteHandler {
+    ///
+    /// Run the handler with the given request
+    ///
+    /// Sync handlers have to get one of the `workers` permits before they start,
+    /// this limits how many blocking threads the server uses at the same time.
+    ///
+    /// It returns JoinError if the handler panicked.
+    ///
+    pub async fn call(&self, request: Request, workers: &Semaphore) -> Result<Response, JoinError> {
+        match self {
+            RouteHandler::Sync(handler) => {
+                // the semaphore is never closed so acquiring a permit can not fail
+                let _permit = workers.acquire().await.unwrap();
+
+                let handler = Arc::clone(handler);
+                tokio::task::spawn_blocking(move || handler(request)).await
+            }
+            RouteHandler::Async(handler) => Ok(handler(request).await),
+        }
+    }
+}
+
+/// Marker for handlers in the form of `Fn(Request) -> Response`, or `Fn(A, B..) -> Response` with extractor arguments
+pub struct SyncHandler;
+
+/// Marker for handlers in the form of `async fn(Request) -> Response`, or `async fn(A, B..) -> Response` with extractor arguments
+pub struct AsyncHandler;
+
+///
+/// Anything that can be registered as a route handler
+///
+/// The `Kind` marker is only there to let both sync closures and async functions
+/// implement this trait, it is inferred by the compiler.
+///
+pub trait IntoRouteHandler<Kind> {
+    fn into_route_handler(self) -> RouteHandler;
+}
+
+impl<F> IntoRouteHandler<SyncHandler> for F
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    fn into_route_handler(self) -> RouteHandler {
+        RouteHandler::Sync(Arc::new(self))
+    }
+}
+
+impl<F, Fut> IntoRouteHandler<AsyncHandler> for F
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    fn into_route_handler(self) -> RouteHandler {
+        RouteHandler::Async(Arc::new(move |request| Box::pin(self(request))))
+    }
+}
+
+///
+/// Implement `IntoRouteHandler` for sync and async handlers taking extractor arguments,
+/// ex; `|Path(id): Path<u32>, Query(search): Query<Search>| -> Response`
+///
+/// The arguments are extracted in order before the handler is called,
+/// the first extraction error is sent as the response.
+///
+macro_rules! impl_extractor_handler {
+    ($($arg:ident $var:ident),+) => {
+        impl<F, $($arg),+> IntoRouteHandler<(SyncHandler, $($arg,)+)> for F
+        where
+            F: Fn($($arg),+) -> Response + Send + Sync + 'static,
+            $($arg: FromRequest,)+
+        {
+            fn into_route_handler(self) -> RouteHandler {
+                RouteHandler::Sync(Arc::new(move |request| {
+                    match extract::<($($arg,)+)>(&request) {
+                        Ok(($($var,)+)) => self($($var),+),
+                        Err(e) => e.into_response(),
+                    }
+                }))
+            }
+        }
+
+        impl<F, Fut, $($arg),+> IntoRouteHandler<(AsyncHandler, $($arg,)+)> for F
+        where
+            F: Fn($($arg),+) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+            $($arg: FromRequest,)+
+        {
+            fn into_route_handler(self) -> RouteHandler {
+                RouteHandler::Async(Arc::new(move |request| {
+                    match extract::<($($arg,)+)>(&request) {
+                        Ok(($($var,)+)) => Box::pin(self($($var),+)),
+                        Err(e) => Box::pin(async move { e.into_response() }),
+                    }
+                }))
+            }
+        }
+
+        impl<$($arg: FromRequest),+> Extract for ($($arg,)+) {
+            fn extract(request: &Request) -> Result<Self, ExtractionError> {
+                Ok(($($arg::from_request(request)?,)+))
+            }
+        }
+    };
+}
+
+/// The handler arguments that can be taken from a request together
+trait Extract: Sized {
+    fn extract(request: &Request) -> Result<Self, ExtractionError>;
+}
+
+fn extract<T: Extract>(request: &Request) -> Result<T, ExtractionError> {
+    T::extract(request)
+}
+
+impl_extractor_handler!(A a);
+impl_extractor_handler!(A a, B b);
+impl_extractor_handler!(A a, B b, C c);
+impl_extractor_handler!(A a, B b, C c, D d);
 
 #[derive(Clone)]
 pub struct Route {
     pub method: HttpMethod,
     pub path: String,
     pub handler: RouteHandler,
+
+    /// the middlewares that only wrap this route, they run after the app middlewares
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+
+    /// the route params captured from the request path, they are set when the route is matched
+    pub params: Vec<RequestParam>,
 }
 
 impl Route {
@@ -17,6 +162,8 @@ impl Route {
             method,
             path,
             handler,
+            middlewares: vec![],
+            params: vec![],
         }
     }
 
@@ -25,15 +172,14 @@ impl Route {
     /// It returns each param is a tuple in the form of (index, name).
     ///
     /// `index` is the index of the route segment in which the param appears.
-    /// `name` is the name of the param.
+    /// `name` is the name of the param, without its `*` catch-all mark, `?` optional mark or constraint.
     ///
     pub fn get_params(&self) -> Vec<(usize, &str)> {
         let params: Vec<_> = self
             .path
             .split('/')
             .enumerate()
-            .filter(|(_, part)| part.starts_with('{'))
-            .map(|(i, name)| (i, &name[1..name.len() - 1]))
+            .filter_map(|(i, part)| Segment::parse(part).param_name().map(|name| (i, name)))
             .collect();
 
         params
@@ -45,9 +191,29 @@ impl Route {
 mod tests {
 
     use super::*;
+    use crate::{
+        app::extract::{Path, Query},
+        http::response::HttpStatusCode,
+    };
+
+    #[derive(serde::Deserialize)]
+    struct Search {
+        q: String,
+    }
+
+    /// A request matched by the `/users/{id}` route
+    async fn user_request(path: &str, id: &str) -> Request {
+        let raw = format!("GET {} HTTP/1.1\r\n", path);
+        let mut request = Request::initial_parse(&mut raw.as_bytes()).await.unwrap();
+        request.route_params = vec![RequestParam {
+            key: "id".to_owned(),
+            value: id.to_owned(),
+        }];
+        request
+    }
 
     fn init_route(path: &str) -> Route {
-        let handler: RouteHandler = Arc::new(|_r: Request| -> Response { todo!() });
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
         Route::new(HttpMethod::Get, path.to_owned(), handler)
     }
 
@@ -94,4 +260,81 @@ mod tests {
         assert_eq!(params[0], (2, "id"));
         assert_eq!(params[1], (4, "comment_id"));
     }
+
+    #[test]
+    fn get_params_work_with_catch_all_and_constrained_params() {
+        let path = "/post/{id:\\d+}/{page?}/{*rest}";
+        let route = init_route(path);
+
+        let params = route.get_params();
+
+        assert_eq!(params, vec![(2, "id"), (3, "page"), (4, "rest")]);
+    }
+
+    #[tokio::test]
+    async fn sync_handler_is_called() {
+        let handler = (|_r: Request| -> Response { Response::ok("sync") }).into_route_handler();
+        let request = Request::initial_parse(&mut "GET / HTTP/1.1\r\n".as_bytes())
+            .await
+            .unwrap();
+
+        let response = handler.call(request, &Semaphore::new(1)).await.unwrap();
+
+        assert_eq!(response.body.as_bytes(), Some("sync".as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn extractor_handlers_are_called_with_the_extracted_arguments() {
+        let sync = (|Path(id): Path<u32>, Query(search): Query<Search>| {
+            Response::ok(&format!("{} {}", id, search.q))
+        })
+        .into_route_handler();
+        let async_handler = (|Path(id): Path<u32>| async move { Response::ok(&id.to_string()) })
+            .into_route_handler();
+
+        let workers = Semaphore::new(1);
+        let response = sync
+            .call(user_request("/users/7?q=rust", "7").await, &workers)
+            .await
+            .unwrap();
+        assert_eq!(response.body.as_bytes(), Some("7 rust".as_bytes()));
+
+        let response = async_handler
+            .call(user_request("/users/7", "7").await, &workers)
+            .await
+            .unwrap();
+        assert_eq!(response.body.as_bytes(), Some("7".as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn extraction_error_is_sent_instead_of_calling_the_handler() {
+        let handler = (|Path(_id): Path<u32>| -> Response { panic!("must not be called") })
+            .into_route_handler();
+
+        let response = handler
+            .call(user_request("/users/me", "me").await, &Semaphore::new(1))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status_code, HttpStatusCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn async_handler_is_called() {
+        async fn handler(_r: Request) -> Response {
+            Response::ok("async")
+        }
+
+        let request = Request::initial_parse(&mut "GET / HTTP/1.1\r\n".as_bytes())
+            .await
+            .unwrap();
+
+        let response = handler
+            .into_route_handler()
+            .call(request, &Semaphore::new(1))
+            .await
+            .unwrap();
+
+        assert_eq!(response.body.as_bytes(), Some("async".as_bytes()));
+    }
 }
@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::http::{
+    http_method::HttpMethod, request_param::RequestParam, url_encoding::decode_path_segment,
+};
+
+use super::route::Route;
+
+///
+/// The registered routes compiled into one prefix tree per HTTP method
+///
+/// Each level of the tree is a path segment, so finding a route takes one step per request
+/// path segment no matter how many routes are registered.
+/// The segments are tried from the most to the least specific, so `/users/me` is matched
+/// before `/users/{id}` whatever the registration order is:
+/// 1. static segments, ex; `me`
+/// 2. params with a regex constraint, ex; `{id:\d+}`
+/// 3. params, ex; `{id}`
+/// 4. catch-all params matching the rest of the path, ex; `{*rest}`
+///
+/// If a branch does not lead to a route the next one is tried, so a request that does not
+/// match a param constraint falls through to the other routes.
+///
+#[derive(Clone, Default)]
+pub struct Router {
+    /// the registered routes, the tree nodes point to them by index
+    routes: Vec<Route>,
+
+    trees: HashMap<HttpMethod, Node>,
+}
+
+#[derive(Clone, Default)]
+struct Node {
+    /// the index of the route that ends at this node, if any
+    route: Option<usize>,
+
+    /// the names of the route params in the order they appear in the path
+    param_names: Vec<String>,
+
+    /// the children of the static segments by segment
+    statics: HashMap<String, Node>,
+
+    /// the children of the constrained params, in the order they are registered
+    constrained: Vec<(Regex, Node)>,
+
+    /// the child of a `{param}` segment, the param name is kept by the node of the route
+    param: Option<Box<Node>>,
+
+    /// the child of a `{*param}` segment, it is always the last segment of a route
+    catch_all: Option<Box<Node>>,
+}
+
+///
+/// A segment of a registered route path
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// a segment that has to be matched as is, ex; `users`
+    Static(&'a str),
+
+    /// a param matching one non-empty segment, ex; `{id}`, `{id:\d+}` or `{page?}`
+    Param {
+        name: &'a str,
+        constraint: Option<&'a str>,
+        optional: bool,
+    },
+
+    /// a param matching the rest of the path, ex; `{*rest}`
+    CatchAll(&'a str),
+}
+
+impl<'a> Segment<'a> {
+    pub fn parse(segment: &'a str) -> Self {
+        let Some(inner) = segment
+            .strip_prefix('{')
+            .and_then(|inner| inner.strip_suffix('}'))
+        else {
+            return Segment::Static(segment);
+        };
+
+        if let Some(name) = inner.strip_prefix('*') {
+            return Segment::CatchAll(name);
+        }
+
+        let (name, constraint) = match inner.split_once(':') {
+            Some((name, constraint)) => (name, Some(constraint)),
+            None => (inner, None),
+        };
+
+        match name.strip_suffix('?') {
+            Some(name) => Segment::Param {
+                name,
+                constraint,
+                optional: true,
+            },
+            None => Segment::Param {
+                name,
+                constraint,
+                optional: false,
+            },
+        }
+    }
+
+    /// The name of the param, None for static segments
+    pub fn param_name(&self) -> Option<&'a str> {
+        match self {
+            Segment::Static(_) => None,
+            Segment::Param { name, .. } | Segment::CatchAll(name) => Some(name),
+        }
+    }
+}
+
+impl Router {
+    ///
+    /// Add a route to the tree of its method
+    ///
+    /// Optional segments (`{page?}`) can only be at the end of the path, the route is added
+    /// with and without each of them, ex; `/posts/{page?}` matches `/posts` and `/posts/2`.
+    ///
+    /// # Panic
+    /// this method will panic if:
+    /// - the route path is already registered
+    /// - the route path is ambiguous with a registered route, ex; `/users/{id}` and `/users/{name}`
+    /// - a catch-all segment is not the last one, or an optional segment is followed by a required one
+    /// - a param constraint is not a valid regex
+    ///
+    pub fn insert(&mut self, route: Route) {
+        let segments: Vec<_> = segments(&route.path).map(Segment::parse).collect();
+        let index = self.routes.len();
+
+        for (i, segment) in segments.iter().enumerate() {
+            if matches!(segment, Segment::CatchAll(_)) && i != segments.len() - 1 {
+                panic!(
+                    "the catch-all segment of `{:?} {}` must be the last one!",
+                    route.method, route.path
+                );
+            }
+            if is_required(segment) && segments[..i].iter().any(|previous| !is_required(previous)) {
+                panic!(
+                    "the optional segments of `{:?} {}` must be at the end of the path!",
+                    route.method, route.path
+                );
+            }
+        }
+
+        // the route is added once without the optional segments, then once more for each of them
+        let required = segments.iter().take_while(|s| is_required(s)).count();
+        for length in required..=segments.len() {
+            // `/posts/{page?}` without its optional segment is `/posts` and not `/posts/`
+            let variant = match &segments[..length] {
+                [] => &[Segment::Static("")][..],
+                variant => variant,
+            };
+
+            self.insert_variant(&route, variant, index);
+        }
+
+        self.routes.push(route);
+    }
+
+    fn insert_variant(&mut self, route: &Route, segments: &[Segment], index: usize) {
+        let mut node = self.trees.entry(route.method.clone()).or_default();
+
+        for segment in segments {
+            node = match segment {
+                Segment::Static(segment) => node.statics.entry(segment.to_string()).or_default(),
+                Segment::Param {
+                    constraint: Some(constraint),
+                    ..
+                } => node.constrained_child(constraint, route),
+                Segment::Param { .. } => node.param.get_or_insert_with(Default::default),
+                Segment::CatchAll(_) => node.catch_all.get_or_insert_with(Default::default),
+            };
+        }
+
+        if let Some(registered) = node.route.map(|index| &self.routes[index]) {
+            if registered.path == route.path {
+                panic!(
+                    "this `{:?} {}` path is already registered!",
+                    route.method, route.path
+                );
+            }
+
+            panic!(
+                "this `{:?} {}` route conflicts with the registered `{:?} {}` route!",
+                route.method, route.path, registered.method, registered.path
+            );
+        }
+
+        node.route = Some(index);
+        node.param_names = segments
+            .iter()
+            .filter_map(|segment| segment.param_name())
+            .map(|name| name.to_owned())
+            .collect();
+    }
+
+    ///
+    /// Find the route that matches a request path, with the route params captured from the path
+    ///
+    /// The path segments are percent-decoded before matching, so the param values are decoded too.
+    ///
+    pub fn find(&self, method: &HttpMethod, path: &str) -> Option<Route> {
+        let segments: Vec<_> = segments(path).map(decode_path_segment).collect();
+        let segments: Vec<_> = segments.iter().map(|segment| segment.as_ref()).collect();
+        let mut values = vec![];
+
+        let node = self.trees.get(method)?.find(&segments, &mut values)?;
+
+        let mut route = self.routes[node.route?].clone();
+        route.params = node
+            .param_names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| RequestParam {
+                key: name.to_owned(),
+                value,
+            })
+            .collect();
+
+        Some(route)
+    }
+
+    ///
+    /// Get the methods that have a route matching a request path
+    ///
+    pub fn methods(&self, path: &str) -> Vec<HttpMethod> {
+        let segments: Vec<_> = segments(path).map(decode_path_segment).collect();
+        let segments: Vec<_> = segments.iter().map(|segment| segment.as_ref()).collect();
+
+        let mut methods: Vec<_> = self
+            .trees
+            .iter()
+            .filter(|(_, tree)| tree.find(&segments, &mut vec![]).is_some())
+            .map(|(method, _)| method.clone())
+            .collect();
+        methods.sort();
+        methods
+    }
+
+    ///
+    /// Get the methods that have at least one route
+    ///
+    pub fn all_methods(&self) -> Vec<HttpMethod> {
+        let mut methods: Vec<_> = self.trees.keys().cloned().collect();
+        methods.sort();
+        methods
+    }
+
+    ///
+    /// Get a registered route by its method and the exact path it was registered with
+    ///
+    pub fn get_mut(&mut self, method: &HttpMethod, path: &str) -> Option<&mut Route> {
+        self.routes
+            .iter_mut()
+            .find(|route| &route.method == method && route.path == path)
+    }
+
+    ///
+    /// Take all the registered routes out of the router
+    ///
+    pub fn into_routes(self) -> Vec<Route> {
+        self.routes
+    }
+}
+
+impl Node {
+    fn constrained_child(&mut self, constraint: &str, route: &Route) -> &mut Node {
+        let position = self
+            .constrained
+            .iter()
+            .position(|(regex, _)| regex.as_str() == anchored(constraint));
+
+        let position = position.unwrap_or_else(|| {
+            let regex = Regex::new(&anchored(constraint)).unwrap_or_else(|e| {
+                panic!(
+                    "the `{}` constraint of `{:?} {}` is not a valid regex: {}",
+                    constraint, route.method, route.path, e
+                )
+            });
+
+            self.constrained.push((regex, Node::default()));
+            self.constrained.len() - 1
+        });
+
+        &mut self.constrained[position].1
+    }
+
+    ///
+    /// Find the node of the route that matches the path segments, the param values are pushed to `values`
+    ///
+    /// The children are tried from the most to the least specific,
+    /// the next one is only tried if the previous ones do not lead to a route.
+    ///
+    fn find(&self, segments: &[&str], values: &mut Vec<String>) -> Option<&Node> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.route.map(|_| self);
+        };
+
+        if let Some(node) = self
+            .statics
+            .get(*segment)
+            .and_then(|child| child.find(rest, values))
+        {
+            return Some(node);
+        }
+
+        // a param never matches an empty segment
+        if !segment.is_empty() {
+            let params = self
+                .constrained
+                .iter()
+                .filter(|(regex, _)| regex.is_match(segment))
+                .map(|(_, child)| child)
+                .chain(self.param.as_deref());
+
+            for child in params {
+                values.push(segment.to_string());
+                if let Some(node) = child.find(rest, values) {
+                    return Some(node);
+                }
+                values.pop();
+            }
+        }
+
+        let node = self.catch_all.as_deref()?;
+        node.route?;
+        values.push(segments.join("/"));
+        Some(node)
+    }
+}
+
+/// Split a path into its segments, `/` is a single empty segment
+fn segments(path: &str) -> std::str::Split<'_, char> {
+    path.strip_prefix('/').unwrap_or(path).split('/')
+}
+
+fn is_required(segment: &Segment) -> bool {
+    !matches!(segment, Segment::Param { optional: true, .. })
+}
+
+/// Make a regex match the whole segment
+fn anchored(constraint: &str) -> String {
+    format!("^(?:{})$", constraint)
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        app::route::IntoRouteHandler,
+        http::{request::Request, response::Response},
+    };
+
+    fn route(method: HttpMethod, path: &str) -> Route {
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        Route::new(method, path.to_owned(), handler)
+    }
+
+    fn router(paths: &[&str]) -> Router {
+        let mut router = Router::default();
+        for path in paths {
+            router.insert(route(HttpMethod::Get, path));
+        }
+        router
+    }
+
+    fn params(route: &Route) -> Vec<(&str, &str)> {
+        route
+            .params
+            .iter()
+            .map(|param| (param.key.as_str(), param.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn static_routes_are_found() {
+        let router = router(&["/", "/about", "/users/all"]);
+
+        assert_eq!(router.find(&HttpMethod::Get, "/").unwrap().path, "/");
+        assert_eq!(
+            router.find(&HttpMethod::Get, "/about").unwrap().path,
+            "/about"
+        );
+        assert_eq!(
+            router.find(&HttpMethod::Get, "/users/all").unwrap().path,
+            "/users/all"
+        );
+        assert!(router.find(&HttpMethod::Get, "/users").is_none());
+        assert!(router.find(&HttpMethod::Get, "/about/").is_none());
+        assert!(router.find(&HttpMethod::Post, "/about").is_none());
+    }
+
+    #[test]
+    fn params_are_captured() {
+        let router = router(&["/post/{id}/comments/{comment_id}"]);
+
+        let route = router.find(&HttpMethod::Get, "/post/1/comments/2").unwrap();
+
+        assert_eq!(params(&route), vec![("id", "1"), ("comment_id", "2")]);
+        assert!(router.find(&HttpMethod::Get, "/post//comments/2").is_none());
+    }
+
+    #[test]
+    fn path_segments_are_percent_decoded() {
+        let router = router(&["/café", "/tags/{tag}", "/files/{*path}"]);
+
+        assert_eq!(
+            router.find(&HttpMethod::Get, "/caf%C3%A9").unwrap().path,
+            "/café"
+        );
+        let route = router
+            .find(&HttpMethod::Get, "/tags/rust%20web+dev")
+            .unwrap();
+        assert_eq!(params(&route), vec![("tag", "rust web+dev")]);
+        let route = router.find(&HttpMethod::Get, "/files/a%20b/c.txt").unwrap();
+        assert_eq!(params(&route), vec![("path", "a b/c.txt")]);
+    }
+
+    #[test]
+    fn static_segments_have_priority_over_params() {
+        let router = router(&["/users/{id}", "/users/me"]);
+
+        let route = router.find(&HttpMethod::Get, "/users/me").unwrap();
+        assert_eq!(route.path, "/users/me");
+        assert!(route.params.is_empty());
+
+        let route = router.find(&HttpMethod::Get, "/users/42").unwrap();
+        assert_eq!(params(&route), vec![("id", "42")]);
+    }
+
+    #[test]
+    fn params_are_tried_when_the_static_branch_does_not_match() {
+        let router = router(&["/users/me/settings", "/users/{id}/posts"]);
+
+        let route = router.find(&HttpMethod::Get, "/users/me/posts").unwrap();
+
+        assert_eq!(route.path, "/users/{id}/posts");
+        assert_eq!(params(&route), vec![("id", "me")]);
+    }
+
+    #[test]
+    fn routes_with_different_param_names_can_share_a_branch() {
+        let router = router(&["/users/{id}/posts", "/users/{user_id}/comments"]);
+
+        let route = router.find(&HttpMethod::Get, "/users/1/comments").unwrap();
+
+        assert_eq!(params(&route), vec![("user_id", "1")]);
+    }
+
+    #[test]
+    fn methods_matching_a_path_are_listed() {
+        let mut router = router(&["/users/{id}"]);
+        router.insert(route(HttpMethod::Delete, "/users/{id}"));
+        router.insert(route(HttpMethod::Post, "/users"));
+
+        assert_eq!(
+            router.methods("/users/1"),
+            vec![HttpMethod::Get, HttpMethod::Delete]
+        );
+        assert_eq!(router.methods("/users"), vec![HttpMethod::Post]);
+        assert!(router.methods("/posts").is_empty());
+    }
+
+    #[test]
+    fn routes_are_found_by_their_registered_path() {
+        let mut router = router(&["/users/{id}", "/users/me"]);
+
+        assert!(router.get_mut(&HttpMethod::Get, "/users/{id}").is_some());
+        assert!(router.get_mut(&HttpMethod::Get, "/users/me").is_some());
+        assert!(router.get_mut(&HttpMethod::Get, "/users/{name}").is_none());
+        assert!(router.get_mut(&HttpMethod::Get, "/users/42").is_none());
+    }
+
+    #[test]
+    fn all_routes_are_taken_out() {
+        let mut paths: Vec<_> = router(&["/", "/users/{id}", "/users/me"])
+            .into_routes()
+            .into_iter()
+            .map(|route| route.path)
+            .collect();
+        paths.sort();
+
+        assert_eq!(paths, vec!["/", "/users/me", "/users/{id}"]);
+    }
+
+    #[test]
+    fn segments_are_parsed() {
+        assert_eq!(Segment::parse("users"), Segment::Static("users"));
+        assert_eq!(Segment::parse("{*rest}"), Segment::CatchAll("rest"));
+        assert_eq!(
+            Segment::parse("{id:\\d+}"),
+            Segment::Param {
+                name: "id",
+                constraint: Some("\\d+"),
+                optional: false
+            }
+        );
+        assert_eq!(
+            Segment::parse("{page?}"),
+            Segment::Param {
+                name: "page",
+                constraint: None,
+                optional: true
+            }
+        );
+    }
+
+    #[test]
+    fn catch_all_matches_the_rest_of_the_path() {
+        let router = router(&["/files/{*path}", "/files/readme"]);
+
+        let route = router
+            .find(&HttpMethod::Get, "/files/css/main.css")
+            .unwrap();
+        assert_eq!(params(&route), vec![("path", "css/main.css")]);
+
+        let route = router.find(&HttpMethod::Get, "/files/readme").unwrap();
+        assert_eq!(route.path, "/files/readme");
+
+        assert!(router.find(&HttpMethod::Get, "/files").is_none());
+    }
+
+    #[test]
+    fn catch_all_is_the_last_resort() {
+        let router = router(&["/{*path}", "/api/users/{id}"]);
+
+        let route = router.find(&HttpMethod::Get, "/api/users/1").unwrap();
+        assert_eq!(params(&route), vec![("id", "1")]);
+
+        let route = router.find(&HttpMethod::Get, "/api/users/1/posts").unwrap();
+        assert_eq!(params(&route), vec![("path", "api/users/1/posts")]);
+
+        let route = router.find(&HttpMethod::Get, "/").unwrap();
+        assert_eq!(params(&route), vec![("path", "")]);
+    }
+
+    #[test]
+    fn constrained_params_fall_through_when_they_do_not_match() {
+        let router = router(&[
+            "/posts/{id:\\d+}",
+            "/posts/{slug:[a-z-]+}",
+            "/posts/{other}",
+        ]);
+
+        let route = router.find(&HttpMethod::Get, "/posts/42").unwrap();
+        assert_eq!(params(&route), vec![("id", "42")]);
+
+        let route = router.find(&HttpMethod::Get, "/posts/hello-world").unwrap();
+        assert_eq!(params(&route), vec![("slug", "hello-world")]);
+
+        let route = router.find(&HttpMethod::Get, "/posts/42abc").unwrap();
+        assert_eq!(params(&route), vec![("other", "42abc")]);
+    }
+
+    #[test]
+    fn constraints_match_the_whole_segment() {
+        let router = router(&["/posts/{id:\\d+}"]);
+
+        assert!(router.find(&HttpMethod::Get, "/posts/42").is_some());
+        assert!(router.find(&HttpMethod::Get, "/posts/a42").is_none());
+        assert!(router.find(&HttpMethod::Get, "/posts/42a").is_none());
+    }
+
+    #[test]
+    fn optional_segments_can_be_omitted() {
+        let router = router(&["/posts/{page?}/{size?:\\d+}"]);
+
+        let route = router.find(&HttpMethod::Get, "/posts").unwrap();
+        assert!(route.params.is_empty());
+
+        let route = router.find(&HttpMethod::Get, "/posts/2").unwrap();
+        assert_eq!(params(&route), vec![("page", "2")]);
+
+        let route = router.find(&HttpMethod::Get, "/posts/2/10").unwrap();
+        assert_eq!(params(&route), vec![("page", "2"), ("size", "10")]);
+
+        assert!(router.find(&HttpMethod::Get, "/posts/2/ten").is_none());
+    }
+
+    #[test]
+    fn optional_root_segment_matches_the_root() {
+        let router = router(&["/{lang?}"]);
+
+        assert!(router
+            .find(&HttpMethod::Get, "/")
+            .unwrap()
+            .params
+            .is_empty());
+        assert_eq!(
+            params(&router.find(&HttpMethod::Get, "/en").unwrap()),
+            vec![("lang", "en")]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with the registered `Get /posts/{page?}` route")]
+    fn optional_segments_are_checked_for_conflicts() {
+        router(&["/posts/{page?}", "/posts"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be the last one")]
+    fn catch_all_in_the_middle_panics() {
+        router(&["/files/{*path}/raw"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be at the end of the path")]
+    fn required_segment_after_an_optional_one_panics() {
+        router(&["/posts/{page?}/all"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a valid regex")]
+    fn invalid_constraint_panics() {
+        router(&["/posts/{id:(}"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn duplicate_routes_panic() {
+        router(&["/users/{id}", "/users/{id}"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with the registered `Get /users/{id}` route")]
+    fn ambiguous_routes_panic() {
+        router(&["/users/{id}", "/users/{name}"]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{app::route::BoxFuture, http::url_encoding::parse_urlencoded};
+
+use super::{
+    random_id,
+    store::{SessionRecord, SessionStore},
+};
+
+/// The extension of the session files
+const EXTENSION: &str = "session";
+
+///
+/// Keep the sessions in a directory, one file per session
+///
+/// A session file has the creation and expiry times (seconds since the UNIX epoch) on its
+/// first line and the URL-encoded session values on its second line, ex;
+/// ```text
+/// 1700000000 1700001800
+/// user=42&theme=dark
+/// ```
+/// Files are replaced atomically, so the directory can be shared by several servers.
+///
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    ///
+    /// Keep the sessions in `dir`, it's created if it does not exist
+    ///
+    pub fn new<P: Into<PathBuf>>(dir: P) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    ///
+    /// The file of a session, None if the ID could escape the directory
+    ///
+    /// IDs come from the session cookie, so only the characters of generated IDs are accepted.
+    ///
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        Some(self.dir.join(format!("{}.{}", id, EXTENSION)))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<Option<SessionRecord>>> {
+        Box::pin(async move {
+            let Some(path) = self.path(id) else {
+                return Ok(None);
+            };
+
+            match tokio::fs::read_to_string(&path).await {
+                Ok(content) => Ok(parse_record(&content)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        record: &'a SessionRecord,
+    ) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            let path = self
+                .path(id)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid session ID"))?;
+
+            // write a temporary file then rename it, so a session file is never half written
+            let temp = self.dir.join(format!(".{}.{}.tmp", id, random_id()));
+            tokio::fs::write(&temp, format_record(record)).await?;
+            if let Err(e) = tokio::fs::rename(&temp, &path).await {
+                let _ = tokio::fs::remove_file(&temp).await;
+                return Err(e);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            let Some(path) = self.path(id) else {
+                return Ok(());
+            };
+
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn sweep(&self, now: SystemTime) -> BoxFuture<'_, std::io::Result<usize>> {
+        Box::pin(async move {
+            let mut swept = 0;
+            let mut entries = tokio::fs::read_dir(&self.dir).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                    continue;
+                }
+
+                // the file may have been deleted by another server since the directory was read
+                let Ok(content) = tokio::fs::read_to_string(&path).await else {
+                    continue;
+                };
+
+                // files that can not be parsed can not be loaded either
+                let expired = parse_record(&content).is_none_or(|record| record.is_expired(now));
+                if expired && remove(&path).await? {
+                    swept += 1;
+                }
+            }
+
+            Ok(swept)
+        })
+    }
+}
+
+/// Remove a file, it returns false if it was already removed
+async fn remove(path: &Path) -> std::io::Result<bool> {
+    match tokio::fs::remove_file(path).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn format_record(record: &SessionRecord) -> String {
+    let secs = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    };
+
+    let mut data = form_urlencoded::Serializer::new(String::new());
+    let mut entries: Vec<_> = record.data.iter().collect();
+    entries.sort();
+    data.extend_pairs(entries);
+
+    format!(
+        "{} {}\n{}\n",
+        secs(record.created_at),
+        secs(record.expires_at),
+        data.finish()
+    )
+}
+
+fn parse_record(content: &str) -> Option<SessionRecord> {
+    let (times, data) = content.split_once('\n')?;
+    let (created_at, expires_at) = times.split_once(' ')?;
+    let time = |secs: &str| -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?))
+    };
+
+    Some(SessionRecord {
+        data: parse_urlencoded(data.trim_end().as_bytes())
+            .into_iter()
+            .map(|param| (param.key, param.value))
+            .collect(),
+        created_at: time(created_at)?,
+        expires_at: time(expires_at)?,
+    })
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn record(expires_in: Duration) -> SessionRecord {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        SessionRecord {
+            data: HashMap::from([
+                ("user".to_owned(), "42".to_owned()),
+                ("name".to_owned(), "Jane Doe & co".to_owned()),
+            ]),
+            created_at: now,
+            expires_at: now + expires_in,
+        }
+    }
+
+    #[test]
+    fn record_is_formatted_and_parsed() {
+        let record = record(Duration::from_secs(1800));
+
+        let content = format_record(&record);
+
+        assert_eq!(
+            content,
+            "1700000000 1700001800\nname=Jane+Doe+%26+co&user=42\n"
+        );
+        assert_eq!(parse_record(&content), Some(record));
+        assert_eq!(parse_record("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn records_are_saved_loaded_and_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileSessionStore::new(dir.path().join("sessions")).unwrap();
+        let record = record(Duration::from_secs(60));
+
+        store.save("abc123", &record).await.unwrap();
+        assert_eq!(store.load("abc123").await.unwrap(), Some(record));
+        assert_eq!(std::fs::read_dir(&store.dir).unwrap().count(), 1);
+
+        store.delete("abc123").await.unwrap();
+        store.delete("abc123").await.unwrap();
+        assert_eq!(store.load("abc123").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn ids_that_could_escape_the_directory_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileSessionStore::new(dir.path()).unwrap();
+
+        assert_eq!(store.load("../secret").await.unwrap(), None);
+        assert!(store
+            .save("../secret", &record(Duration::ZERO))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn sweep_deletes_expired_and_broken_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileSessionStore::new(dir.path()).unwrap();
+        store
+            .save("fresh", &record(Duration::from_secs(60)))
+            .await
+            .unwrap();
+        store.save("old", &record(Duration::ZERO)).await.unwrap();
+        std::fs::write(dir.path().join("broken.session"), "garbage").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a session").unwrap();
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_030);
+        assert_eq!(store.sweep(now).await.unwrap(), 2);
+        assert!(store.load("fresh").await.unwrap().is_some());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::Mutex, time::SystemTime};
+
+use crate::app::route::BoxFuture;
+
+use super::store::{SessionRecord, SessionStore};
+
+///
+/// Keep the sessions in memory
+///
+/// The sessions are lost when the server stops and are not shared between servers,
+/// use `FileSessionStore` or another store to keep them.
+///
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    records: Mutex<HashMap<String, SessionRecord>>,
+}
+
+impl MemorySessionStore {
+    /// The number of stored sessions, including the expired ones not swept yet
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<Option<SessionRecord>>> {
+        let record = self.records.lock().unwrap().get(id).cloned();
+        Box::pin(async move { Ok(record) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        record: &'a SessionRecord,
+    ) -> BoxFuture<'a, std::io::Result<()>> {
+        self.records
+            .lock()
+            .unwrap()
+            .insert(id.to_owned(), record.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        self.records.lock().unwrap().remove(id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn sweep(&self, now: SystemTime) -> BoxFuture<'_, std::io::Result<usize>> {
+        let mut records = self.records.lock().unwrap();
+        let before = records.len();
+        records.retain(|_, record| !record.is_expired(now));
+        let swept = before - records.len();
+
+        Box::pin(async move { Ok(swept) })
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+
+    fn record(expires_in: Duration) -> SessionRecord {
+        let now = SystemTime::now();
+        SessionRecord {
+            data: HashMap::from([("user".to_owned(), "42".to_owned())]),
+            created_at: now,
+            expires_at: now + expires_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn records_are_saved_loaded_and_deleted() {
+        let store = MemorySessionStore::default();
+        let record = record(Duration::from_secs(60));
+
+        store.save("a", &record).await.unwrap();
+        assert_eq!(store.load("a").await.unwrap(), Some(record));
+
+        store.delete("a").await.unwrap();
+        assert_eq!(store.load("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn sweep_deletes_expired_records() {
+        let store = MemorySessionStore::default();
+        store
+            .save("fresh", &record(Duration::from_secs(60)))
+            .await
+            .unwrap();
+        store.save("old", &record(Duration::ZERO)).await.unwrap();
+
+        assert_eq!(store.sweep(SystemTime::now()).await.unwrap(), 1);
+        assert_eq!(store.len(), 1);
+        assert!(store.load("fresh").await.unwrap().is_some());
+    }
+}
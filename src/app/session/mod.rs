@@ -0,0 +1,563 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex, Once, Weak},
+    time::{Duration, SystemTime},
+};
+
+use crate::http::{
+    cookie::{Cookie, SameSite},
+    request::Request,
+    response::Response,
+};
+
+use self::store::{SessionRecord, SessionStore};
+
+use super::{
+    middleware::{Middleware, Next},
+    route::BoxFuture,
+};
+
+pub mod file_store;
+pub mod memory_store;
+pub mod store;
+
+///
+/// The session of the current request, shared by the middlewares and the route handler
+///
+/// Get it with `request.session()` or the `Session` extractor, changes are saved
+/// by the session middleware after the handler returns.
+/// A session is only stored (and its cookie sent) once it has a value.
+///
+#[derive(Debug, Clone)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    /// the ID of the stored session, None for a new session
+    id: Option<String>,
+    data: HashMap<String, String>,
+    created_at: SystemTime,
+
+    /// the session gets a new ID when it's saved
+    rotate: bool,
+
+    /// the session is deleted when the handler returns
+    destroyed: bool,
+}
+
+impl Session {
+    fn new(id: Option<String>, record: Option<SessionRecord>) -> Self {
+        let (data, created_at) = match record {
+            Some(record) => (record.data, record.created_at),
+            None => (HashMap::new(), SystemTime::now()),
+        };
+
+        Self {
+            state: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                created_at,
+                rotate: false,
+                destroyed: false,
+            })),
+        }
+    }
+
+    /// The ID of the stored session, None for a new session
+    pub fn id(&self) -> Option<String> {
+        self.state.lock().unwrap().id.clone()
+    }
+
+    ///
+    /// Get a session value by its key and type
+    ///
+    /// It returns None if no value exist with the same key
+    /// or if the value exist but can not be parsed to the specified type T
+    ///
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.state.lock().unwrap().data.get(key)?.parse().ok()
+    }
+
+    /// Set a session value, replacing any existing value with the same key
+    pub fn insert<V: ToString>(&self, key: &str, value: V) {
+        self.state
+            .lock()
+            .unwrap()
+            .data
+            .insert(key.to_owned(), value.to_string());
+    }
+
+    /// Remove a session value and return it
+    pub fn remove(&self, key: &str) -> Option<String> {
+        self.state.lock().unwrap().data.remove(key)
+    }
+
+    /// Remove all the session values, the session is kept
+    pub fn clear(&self) {
+        self.state.lock().unwrap().data.clear();
+    }
+
+    ///
+    /// Give the session a new ID, keeping its values
+    ///
+    /// Rotate the ID when the user privileges change (ex; after login) so an ID
+    /// known before can not be used to take over the session (session fixation).
+    ///
+    pub fn rotate(&self) {
+        self.state.lock().unwrap().rotate = true;
+    }
+
+    ///
+    /// Delete the session from the store and remove its cookie from the client, ex; on logout
+    ///
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+///
+/// How sessions are identified and when they expire
+///
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    cookie: Cookie,
+    idle_timeout: Duration,
+    absolute_timeout: Duration,
+    sweep_interval: Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            cookie: Cookie::new("session_id", "")
+                .set_path("/")
+                .set_http_only(true)
+                .set_same_site(SameSite::Lax),
+            idle_timeout: Duration::from_secs(30 * 60),
+            absolute_timeout: Duration::from_secs(24 * 60 * 60),
+            sweep_interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl SessionConfig {
+    ///
+    /// Set the cookie the session ID is sent in, its value is ignored
+    ///
+    /// The default cookie is `session_id` with `Path=/; HttpOnly; SameSite=Lax`,
+    /// ex; `Cookie::new("sid", "").set_path("/").set_http_only(true).set_secure(true)` for HTTPS apps.
+    ///
+    pub fn set_cookie(mut self, cookie: Cookie) -> Self {
+        self.cookie = cookie;
+        self
+    }
+
+    ///
+    /// Set how long a session lives without being used, default is 30 minutes
+    ///
+    pub fn set_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    ///
+    /// Set how long a session lives after it's created even if it's used, default is 24 hours
+    ///
+    pub fn set_absolute_timeout(mut self, absolute_timeout: Duration) -> Self {
+        self.absolute_timeout = absolute_timeout;
+        self
+    }
+
+    ///
+    /// Set how often the expired sessions are deleted from the store, default is 5 minutes
+    ///
+    /// # Panic
+    /// this method will panic if `sweep_interval` is zero
+    ///
+    pub fn set_sweep_interval(mut self, sweep_interval: Duration) -> Self {
+        assert!(
+            !sweep_interval.is_zero(),
+            "the sessions sweep interval must not be zero"
+        );
+
+        self.sweep_interval = sweep_interval;
+        self
+    }
+}
+
+///
+/// Load the request session from the store and save it after the handler, see `App::sessions`
+///
+/// The first request also starts the sweeper task that deletes the expired sessions
+/// every `sweep_interval`, it stops when the app is dropped.
+///
+pub struct SessionMiddleware {
+    store: Arc<dyn SessionStore>,
+    config: SessionConfig,
+    sweeper: Once,
+}
+
+impl SessionMiddleware {
+    pub fn new<S: SessionStore>(store: S, config: SessionConfig) -> Self {
+        Self {
+            store: Arc::new(store),
+            config,
+            sweeper: Once::new(),
+        }
+    }
+
+    fn start_sweeper(&self) {
+        let store = Arc::downgrade(&self.store);
+        let interval = self.config.sweep_interval;
+
+        self.sweeper.call_once(|| {
+            tokio::spawn(sweep(store, interval));
+        });
+    }
+
+    ///
+    /// Get the session of the request, a new one if the cookie has no valid session
+    ///
+    async fn load(&self, request: &Request, now: SystemTime) -> std::io::Result<Session> {
+        let Some(cookie) = request.cookie(&self.config.cookie.name) else {
+            return Ok(Session::new(None, None));
+        };
+
+        let record = self.store.load(&cookie.value).await?;
+        match record {
+            Some(record)
+                if !record.is_expired(now)
+                    && record.created_at + self.config.absolute_timeout > now =>
+            {
+                Ok(Session::new(Some(cookie.value), Some(record)))
+            }
+            Some(_) => {
+                self.store.delete(&cookie.value).await?;
+                Ok(Session::new(None, None))
+            }
+            None => Ok(Session::new(None, None)),
+        }
+    }
+
+    ///
+    /// Save the session changes and send its cookie if the ID changed
+    ///
+    /// A new ID is always generated for new sessions, so a client can not choose its session ID.
+    /// `has_cookie` tells if the client sent a session cookie, valid or not, to remove it when needed.
+    ///
+    async fn save(
+        &self,
+        session: &Session,
+        has_cookie: bool,
+        response: &mut Response,
+        now: SystemTime,
+    ) -> std::io::Result<()> {
+        let (old_id, record, rotate, destroyed) = {
+            let state = session.state.lock().unwrap();
+            let expires_at = (now + self.config.idle_timeout)
+                .min(state.created_at + self.config.absolute_timeout);
+            let record = SessionRecord {
+                data: state.data.clone(),
+                created_at: state.created_at,
+                expires_at,
+            };
+
+            (state.id.clone(), record, state.rotate, state.destroyed)
+        };
+
+        // empty sessions are not stored, so visitors without a session do not get a cookie
+        if destroyed || record.data.is_empty() {
+            if let Some(id) = old_id {
+                self.store.delete(&id).await?;
+            }
+            if has_cookie {
+                response.set_cookie(&self.config.cookie.clone().removal());
+            }
+            return Ok(());
+        }
+
+        let id = match old_id {
+            Some(id) if !rotate => id,
+            old_id => {
+                if let Some(old_id) = old_id {
+                    self.store.delete(&old_id).await?;
+                }
+
+                let id = random_id();
+                let mut cookie = self.config.cookie.clone();
+                cookie.value = id.clone();
+                response.set_cookie(&cookie);
+                id
+            }
+        };
+
+        self.store.save(&id, &record).await
+    }
+}
+
+impl Middleware for SessionMiddleware {
+    fn handle<'a>(&'a self, mut request: Request, next: Next) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            self.start_sweeper();
+
+            let has_cookie = request.cookie(&self.config.cookie.name).is_some();
+            let session = match self.load(&request, SystemTime::now()).await {
+                Ok(session) => session,
+                Err(e) => {
+                    eprintln!("Loading the session failed: {}", e);
+                    return Response::server_error();
+                }
+            };
+
+            request.session = Some(session.clone());
+            let mut response = next.run(request).await;
+
+            match self
+                .save(&session, has_cookie, &mut response, SystemTime::now())
+                .await
+            {
+                Ok(_) => response,
+                Err(e) => {
+                    eprintln!("Saving the session failed: {}", e);
+                    Response::server_error()
+                }
+            }
+        })
+    }
+}
+
+/// Delete the expired sessions every `interval` until the store is dropped
+async fn sweep(store: Weak<dyn SessionStore>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // the first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        if let Err(e) = store.sweep(SystemTime::now()).await {
+            eprintln!("Sweeping the expired sessions failed: {}", e);
+        }
+    }
+}
+
+/// A random 256 bits ID, hex encoded
+pub(crate) fn random_id() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).expect("the OS random number generator failed");
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use tokio::sync::Semaphore;
+
+    use super::*;
+    use crate::app::{extract::FromRequest, App};
+
+    use self::memory_store::MemorySessionStore;
+
+    async fn send(app: &App, path: &str, cookie: Option<&str>) -> Response {
+        let raw = match cookie {
+            Some(cookie) => format!("GET {} HTTP/1.1\r\nCookie: {}\r\n\r\n", path, cookie),
+            None => format!("GET {} HTTP/1.1\r\n\r\n", path),
+        };
+
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        let route = app.get_route(&request.method, &request.base_path);
+        request
+            .complete_parsing(&mut reader, route.as_ref(), 1024)
+            .await
+            .unwrap();
+
+        app.dispatch(request, route, Arc::new(Semaphore::new(1)))
+            .await
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    /// The `session_id=..` pair of the response Set-Cookie header
+    fn session_cookie(response: &Response) -> Option<String> {
+        let value = response.get_header("Set-Cookie")?;
+        value.split(';').next().map(|pair| pair.to_owned())
+    }
+
+    fn app(store: &Arc<MemorySessionStore>, config: SessionConfig) -> App {
+        App::default()
+            .sessions(Arc::clone(store), config)
+            .get("/", |session: Session| {
+                Response::ok(&session.get::<String>("user").unwrap_or_default())
+            })
+            .get("/login", |session: Session| {
+                session.insert("user", "jane");
+                session.rotate();
+                Response::ok("")
+            })
+            .get("/visit", |session: Session| {
+                let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+                session.insert("visits", visits);
+                Response::ok(&visits.to_string())
+            })
+            .get("/logout", |session: Session| {
+                session.destroy();
+                Response::ok("")
+            })
+    }
+
+    #[tokio::test]
+    async fn empty_sessions_are_not_stored() {
+        let store = Arc::new(MemorySessionStore::default());
+        let app = app(&store, SessionConfig::default());
+
+        let response = send(&app, "/", None).await;
+
+        assert_eq!(response.get_header("Set-Cookie"), None);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_values_are_kept_between_requests() {
+        let store = Arc::new(MemorySessionStore::default());
+        let app = app(&store, SessionConfig::default());
+
+        let response = send(&app, "/visit", None).await;
+        let cookie = session_cookie(&response).unwrap();
+        assert!(response
+            .get_header("Set-Cookie")
+            .unwrap()
+            .ends_with("; Path=/; HttpOnly; SameSite=Lax"));
+
+        let response = send(&app, "/visit", Some(&cookie)).await;
+
+        assert_eq!(body(&response), "2");
+        assert_eq!(response.get_header("Set-Cookie"), None);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rotation_gives_a_new_id_and_keeps_the_values() {
+        let store = Arc::new(MemorySessionStore::default());
+        let app = app(&store, SessionConfig::default());
+        let first = session_cookie(&send(&app, "/visit", None).await).unwrap();
+
+        let second = session_cookie(&send(&app, "/login", Some(&first)).await).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(store.len(), 1);
+        assert_eq!(body(&send(&app, "/", Some(&second)).await), "jane");
+        assert_eq!(body(&send(&app, "/visit", Some(&second)).await), "2");
+        assert_eq!(body(&send(&app, "/", Some(&first)).await), "");
+    }
+
+    #[tokio::test]
+    async fn unknown_ids_are_replaced_by_a_new_one() {
+        let store = Arc::new(MemorySessionStore::default());
+        let app = app(&store, SessionConfig::default());
+
+        let response = send(&app, "/visit", Some("session_id=chosenbyattacker")).await;
+
+        let cookie = session_cookie(&response).unwrap();
+        assert_ne!(cookie, "session_id=chosenbyattacker");
+        assert_eq!(cookie.len(), "session_id=".len() + 64);
+        assert!(store.load("chosenbyattacker").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn destroyed_session_is_deleted_with_its_cookie() {
+        let store = Arc::new(MemorySessionStore::default());
+        let app = app(&store, SessionConfig::default());
+        let cookie = session_cookie(&send(&app, "/login", None).await).unwrap();
+
+        let response = send(&app, "/logout", Some(&cookie)).await;
+
+        assert_eq!(
+            response.get_header("Set-Cookie"),
+            Some("session_id=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT; HttpOnly; SameSite=Lax")
+        );
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_expire() {
+        let store = Arc::new(MemorySessionStore::default());
+        let config = SessionConfig::default().set_idle_timeout(Duration::from_millis(50));
+        let app = app(&store, config);
+        let cookie = session_cookie(&send(&app, "/login", None).await).unwrap();
+
+        assert_eq!(body(&send(&app, "/", Some(&cookie)).await), "jane");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = send(&app, "/", Some(&cookie)).await;
+
+        assert_eq!(body(&response), "");
+        assert!(response
+            .get_header("Set-Cookie")
+            .unwrap()
+            .contains("Max-Age=0"));
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sessions_expire_after_the_absolute_timeout_even_if_used() {
+        let store = Arc::new(MemorySessionStore::default());
+        let config = SessionConfig::default().set_absolute_timeout(Duration::from_millis(100));
+        let app = app(&store, config);
+        let cookie = session_cookie(&send(&app, "/visit", None).await).unwrap();
+
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            send(&app, "/visit", Some(&cookie)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert_eq!(body(&send(&app, "/visit", Some(&cookie)).await), "1");
+    }
+
+    #[test]
+    #[should_panic(expected = "the sessions sweep interval must not be zero")]
+    fn zero_sweep_interval_is_rejected() {
+        let _ = SessionConfig::default().set_sweep_interval(Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn sweeper_deletes_expired_sessions() {
+        let store = Arc::new(MemorySessionStore::default());
+        let config = SessionConfig::default()
+            .set_idle_timeout(Duration::from_millis(20))
+            .set_sweep_interval(Duration::from_millis(10));
+        let app = app(&store, config);
+
+        send(&app, "/visit", None).await;
+        assert_eq!(store.len(), 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_extractor_needs_sessions() {
+        let request = Request::initial_parse(&mut "GET / HTTP/1.1\r\n".as_bytes())
+            .await
+            .unwrap();
+
+        let error = Session::from_request(&request).unwrap_err();
+
+        assert_eq!(
+            error.status_code,
+            crate::http::response::HttpStatusCode::ServerError
+        );
+    }
+}
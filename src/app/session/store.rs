@@ -0,0 +1,71 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use crate::app::route::BoxFuture;
+
+///
+/// What is stored for a session between requests
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRecord {
+    /// the session values by key
+    pub data: HashMap<String, String>,
+
+    /// when the session was created, it's kept when the session ID is rotated
+    pub created_at: SystemTime,
+
+    /// when the session expires if it's not used again, stores can delete it after this time
+    pub expires_at: SystemTime,
+}
+
+impl SessionRecord {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at <= now
+    }
+}
+
+///
+/// Where the sessions are kept, ex; `MemorySessionStore` or `FileSessionStore`
+///
+/// Stores do not check expiry when loading a record, the session middleware does,
+/// but they must delete the expired records when `sweep` is called.
+///
+pub trait SessionStore: Send + Sync + 'static {
+    /// Get the record of a session, None if the session does not exist
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<Option<SessionRecord>>>;
+
+    /// Create or replace the record of a session
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        record: &'a SessionRecord,
+    ) -> BoxFuture<'a, std::io::Result<()>>;
+
+    /// Delete a session, deleting a missing session is not an error
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<()>>;
+
+    /// Delete the sessions expired at `now`, it returns how many were deleted
+    fn sweep(&self, now: SystemTime) -> BoxFuture<'_, std::io::Result<usize>>;
+}
+
+/// A shared store, so the app can keep using the store given to `App::sessions`
+impl<S: SessionStore> SessionStore for Arc<S> {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<Option<SessionRecord>>> {
+        self.as_ref().load(id)
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        record: &'a SessionRecord,
+    ) -> BoxFuture<'a, std::io::Result<()>> {
+        self.as_ref().save(id, record)
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        self.as_ref().delete(id)
+    }
+
+    fn sweep(&self, now: SystemTime) -> BoxFuture<'_, std::io::Result<usize>> {
+        self.as_ref().sweep(now)
+    }
+}
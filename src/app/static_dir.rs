@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+
+use crate::http::{
+    request::Request,
+    response::{HttpStatusCode, Response},
+    url_encoding::decode_path_segment,
+};
+
+use super::{normalize_prefix, strip_path_prefix};
+
+/// The file served when a directory is requested
+const INDEX_FILE: &str = "index.html";
+
+/// The page served (with 404 status) when the requested file does not exist
+const NOT_FOUND_PAGE: &str = "404.html";
+
+///
+/// Maps a URL prefix onto a directory, so `GET /assets/css/main.css`
+/// serves `static/css/main.css` for `StaticDir::new("/assets", "static/")`
+///
+#[derive(Debug, Clone)]
+pub struct StaticDir {
+    /// The URL prefix without the trailing slash, it's empty for `/`
+    pub prefix: String,
+
+    /// The directory the files are served from
+    pub root: PathBuf,
+}
+
+impl StaticDir {
+    pub fn new(prefix: &str, root: &str) -> Self {
+        Self {
+            prefix: normalize_prefix(prefix),
+            root: PathBuf::from(root),
+        }
+    }
+
+    ///
+    /// Get the path relative to the served directory
+    ///
+    /// It returns None if the path is not under this directory URL prefix
+    ///
+    pub fn strip_prefix<'a>(&self, path: &'a str) -> Option<&'a str> {
+        strip_path_prefix(&self.prefix, path)
+    }
+
+    ///
+    /// Serve the file the request path points to
+    ///
+    /// - directories are served using their `index.html`
+    /// - `..` segments and symlinks pointing outside the directory are refused with 403
+    /// - missing files are answered with the directory `404.html` page
+    ///
+    pub fn serve(&self, request: &Request) -> Response {
+        let Some(relative) = self.strip_prefix(&request.base_path) else {
+            return self.not_found();
+        };
+
+        let mut path = self.root.clone();
+        for segment in relative.split('/').map(decode_path_segment) {
+            match segment.as_ref() {
+                "" | "." => continue,
+                ".." => return Response::forbidden(),
+                // an encoded slash must not add a level, and a backslash is a path separator on windows
+                segment if segment.contains(['/', '\\']) => return Response::forbidden(),
+                segment => path.push(segment),
+            }
+        }
+
+        if path.is_dir() {
+            // relative links in the index page only work if the directory path ends with a slash
+            if !request.base_path.ends_with('/') {
+                let location = match request.full_path.split_once('?') {
+                    Some((path, query)) => format!("{}/?{}", path, query),
+                    None => format!("{}/", request.full_path),
+                };
+                return Response::moved_permanently(&location);
+            }
+
+            path.push(INDEX_FILE);
+        }
+
+        match self.is_inside_root(&path) {
+            Ok(true) => {}
+            Ok(false) => return Response::forbidden(),
+            Err(_) => return self.not_found(),
+        }
+
+        match Response::from_file(&path, HttpStatusCode::Ok) {
+            Ok(response) => response.conditional(request).ranged(request),
+            Err(_) => self.not_found(),
+        }
+    }
+
+    ///
+    /// Check the real path of the file (after resolving symlinks) is still inside the served directory
+    ///
+    /// It fails if the file does not exist.
+    ///
+    fn is_inside_root(&self, path: &Path) -> std::io::Result<bool> {
+        Ok(path.canonicalize()?.starts_with(self.root.canonicalize()?))
+    }
+
+    fn not_found(&self) -> Response {
+        Response::from_file(self.root.join(NOT_FOUND_PAGE), HttpStatusCode::NotFound)
+            .unwrap_or_else(|_| Response::not_found())
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::http::response_body::ResponseBody;
+
+    async fn request(path: &str) -> Request {
+        let raw = format!("GET {} HTTP/1.1\r\n", path);
+        Request::initial_parse(&mut raw.as_bytes()).await.unwrap()
+    }
+
+    fn body(response: Response) -> String {
+        match response.body {
+            ResponseBody::File { file, .. } => std::io::read_to_string(file).unwrap(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn prefix_is_normalized() {
+        assert_eq!(StaticDir::new("/assets/", "static").prefix, "/assets");
+        assert_eq!(StaticDir::new("assets", "static").prefix, "/assets");
+        assert_eq!(StaticDir::new("/", "static").prefix, "");
+    }
+
+    #[test]
+    fn strip_prefix_matches_whole_segments() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        assert_eq!(dir.strip_prefix("/assets/a.css"), Some("/a.css"));
+        assert_eq!(dir.strip_prefix("/assets"), Some(""));
+        assert_eq!(dir.strip_prefix("/assetsfoo"), None);
+        assert_eq!(dir.strip_prefix("/other"), None);
+    }
+
+    #[tokio::test]
+    async fn file_is_served_with_its_mime_type() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets/index.html").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert!(body(response).contains("Hi from Rust"));
+    }
+
+    #[tokio::test]
+    async fn directory_is_served_using_its_index() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets/").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+        assert!(body(response).contains("Hi from Rust"));
+    }
+
+    #[tokio::test]
+    async fn directory_without_trailing_slash_is_redirected() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets?v=1").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::MovedPermanently);
+        assert_eq!(response.get_header("Location"), Some("/assets/?v=1"));
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_served_with_404_page() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets/missing.css").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::NotFound);
+        assert!(body(response).contains("Oops!"));
+    }
+
+    #[tokio::test]
+    async fn parent_segments_are_refused() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets/../Cargo.toml").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::Forbidden);
+    }
+
+    #[tokio::test]
+    async fn encoded_parent_segments_and_slashes_are_refused() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let parent = dir.serve(&request("/assets/%2E%2E/Cargo.toml").await);
+        let slash = dir.serve(&request("/assets/..%2FCargo.toml").await);
+
+        assert_eq!(parent.status_code, HttpStatusCode::Forbidden);
+        assert_eq!(slash.status_code, HttpStatusCode::Forbidden);
+    }
+
+    #[tokio::test]
+    async fn encoded_file_name_is_decoded() {
+        let dir = StaticDir::new("/assets", "static/");
+
+        let response = dir.serve(&request("/assets/%69ndex.html").await);
+
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_outside_the_directory_are_refused() {
+        let root = std::env::temp_dir().join(format!("rs-server-static-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let link = root.join("cargo.toml");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(Path::new("Cargo.toml").canonicalize().unwrap(), &link).unwrap();
+
+        let dir = StaticDir::new("/", root.to_str().unwrap());
+        let response = dir.serve(&request("/cargo.toml").await);
+
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(response.status_code, HttpStatusCode::Forbidden);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fs::Metadata, time::SystemTime};
+
+///
+/// Build a strong entity tag for a file from its modification time and size
+///
+pub fn file_etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default();
+
+    format!("\"{:x}-{:x}\"", modified, metadata.len())
+}
+
+///
+/// Check if an entity tag is in an `If-None-Match` list using the weak comparison
+///
+/// `*` matches any entity tag.
+///
+pub fn etag_matches_any(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    list.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+///
+/// Check if two entity tags are the same using the strong comparison, weak tags never match
+///
+pub fn etag_strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && a == b
+}
+
+///
+/// Check if an `If-Range` value still matches the representation validators (RFC 9110 section 13.1.5)
+///
+/// The value is either an entity tag compared using the strong comparison,
+/// or an HTTP-date that must be exactly the `Last-Modified` date.
+///
+pub fn if_range_matches(if_range: &str, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return etag.is_some_and(|etag| etag_strong_eq(if_range, etag));
+    }
+
+    match (
+        last_modified.map(httpdate::parse_http_date),
+        httpdate::parse_http_date(if_range),
+    ) {
+        (Some(Ok(last_modified)), Ok(date)) => last_modified == date,
+        _ => false,
+    }
+}
+
+///
+/// Check if the resource was not modified after the given HTTP-date
+///
+/// It returns false if any of the dates can not be parsed.
+///
+pub fn not_modified_since(last_modified: &str, since: &str) -> bool {
+    match (
+        httpdate::parse_http_date(last_modified),
+        httpdate::parse_http_date(since),
+    ) {
+        (Ok(last_modified), Ok(since)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn etag_matches_any_uses_weak_comparison() {
+        assert!(etag_matches_any("\"a\", W/\"b\"", "\"b\""));
+        assert!(etag_matches_any("W/\"a\"", "\"a\""));
+        assert!(etag_matches_any("*", "\"a\""));
+        assert!(!etag_matches_any("\"a\"", "\"b\""));
+    }
+
+    #[test]
+    fn etag_strong_eq_refuses_weak_tags() {
+        assert!(etag_strong_eq("\"a\"", "\"a\""));
+        assert!(!etag_strong_eq("W/\"a\"", "W/\"a\""));
+        assert!(!etag_strong_eq("\"a\"", "\"b\""));
+    }
+
+    #[test]
+    fn if_range_matches_etag_or_exact_date() {
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+
+        assert!(if_range_matches("\"a\"", Some("\"a\""), None));
+        assert!(!if_range_matches("W/\"a\"", Some("W/\"a\""), None));
+        assert!(if_range_matches(date, None, Some(date)));
+        assert!(!if_range_matches(
+            date,
+            Some("\"a\""),
+            Some("Thu, 22 Oct 2015 07:28:00 GMT")
+        ));
+    }
+
+    #[test]
+    fn not_modified_since_compares_dates() {
+        let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+
+        assert!(not_modified_since(
+            last_modified,
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        ));
+        assert!(not_modified_since(
+            last_modified,
+            "Thu, 22 Oct 2015 07:28:00 GMT"
+        ));
+        assert!(!not_modified_since(
+            last_modified,
+            "Tue, 20 Oct 2015 07:28:00 GMT"
+        ));
+        assert!(!not_modified_since(last_modified, "yesterday"));
+    }
+}
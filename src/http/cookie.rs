@@ -0,0 +1,282 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+use super::http_header::is_tchar;
+
+/// The characters that are not allowed in a cookie value (RFC 6265 section 4.1.1), `%` is the escape
+const COOKIE_VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b',')
+    .add(b';')
+    .add(b'\\')
+    .add(b'%');
+
+///
+/// The `SameSite` attribute of a cookie, it controls if the cookie is sent with cross-site requests
+///
+/// `None` needs the cookie to be `Secure` or browsers reject it.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+///
+/// An HTTP cookie, received in the `Cookie` request header or sent in a `Set-Cookie` response header
+///
+/// Cookies received from the client only have a name and a value, the attributes are only sent.
+/// Values are percent-encoded when sent and decoded when received, so they can hold any text.
+///
+/// ex; `Cookie::new("theme", "dark").set_path("/").set_max_age(Duration::from_secs(3600)).set_http_only(true)`
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+
+    /// the path the cookie is sent for, the path of the request URL if None
+    pub path: Option<String>,
+
+    /// the host the cookie is sent to with its subdomains, only the request host if None
+    pub domain: Option<String>,
+
+    /// how long the cookie lives, it takes precedence over `expires`
+    pub max_age: Option<Duration>,
+
+    /// when the cookie expires, it lives until the browser is closed if both `max_age` and `expires` are None
+    pub expires: Option<SystemTime>,
+
+    /// the cookie is only sent over HTTPS
+    pub secure: bool,
+
+    /// the cookie is not visible to JavaScript
+    pub http_only: bool,
+
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    ///
+    /// Create a cookie without attributes
+    ///
+    /// # Panic
+    ///
+    /// If the name is empty or is not a token (ex; it has spaces, `=` or `;`)
+    ///
+    pub fn new(name: &str, value: &str) -> Self {
+        if name.is_empty() || !name.bytes().all(is_tchar) {
+            panic!("`{}` is not a valid cookie name", name);
+        }
+
+        Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    pub fn set_path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_owned());
+        self
+    }
+
+    pub fn set_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_owned());
+        self
+    }
+
+    pub fn set_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn set_expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn set_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn set_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn set_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    ///
+    /// Turn the cookie into one that removes it from the client
+    ///
+    /// The path and the domain are kept since the client only removes the cookie if they match.
+    ///
+    pub fn removal(mut self) -> Self {
+        self.value = String::new();
+        self.max_age = Some(Duration::ZERO);
+        self.expires = Some(SystemTime::UNIX_EPOCH);
+        self
+    }
+
+    /// Check if the cookie removes the client cookie, see `removal`
+    pub fn is_removal(&self) -> bool {
+        self.max_age == Some(Duration::ZERO)
+    }
+
+    ///
+    /// Parse the cookies of a `Cookie` request header, ex; `theme=dark; lang=en`
+    ///
+    /// Pairs without a name or an `=` are skipped, and quoted values are unquoted.
+    ///
+    pub fn parse_header(value: &str) -> Vec<Cookie> {
+        value
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .filter(|(name, _)| !name.is_empty() && name.bytes().all(is_tchar))
+            .map(|(name, value)| {
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+
+                Cookie::new(name, &percent_decode_str(value).decode_utf8_lossy())
+            })
+            .collect()
+    }
+}
+
+/// The `Set-Cookie` header value
+impl Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={}",
+            self.name,
+            utf8_percent_encode(&self.value, COOKIE_VALUE)
+        )?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = &self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = &self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(*expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = &self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn cookie_without_attributes_is_formatted() {
+        assert_eq!(Cookie::new("theme", "dark").to_string(), "theme=dark");
+    }
+
+    #[test]
+    fn all_attributes_are_formatted() {
+        let cookie = Cookie::new("session", "abc")
+            .set_path("/")
+            .set_domain("example.com")
+            .set_max_age(Duration::from_secs(3600))
+            .set_expires(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))
+            .set_secure(true)
+            .set_http_only(true)
+            .set_same_site(SameSite::Lax);
+
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc; Path=/; Domain=example.com; Max-Age=3600; \
+            Expires=Sun, 06 Nov 1994 08:49:37 GMT; Secure; HttpOnly; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    fn value_is_percent_encoded() {
+        let cookie = Cookie::new("name", "Jane Doe; \"50%\"");
+
+        assert_eq!(cookie.to_string(), "name=Jane%20Doe%3B%20%2250%25%22");
+        assert_eq!(
+            Cookie::parse_header("name=Jane%20Doe%3B%20%2250%25%22")[0].value,
+            "Jane Doe; \"50%\""
+        );
+    }
+
+    #[test]
+    fn removal_cookie_expires_now() {
+        let cookie = Cookie::new("session", "abc").set_path("/").removal();
+
+        assert!(cookie.is_removal());
+        assert_eq!(
+            cookie.to_string(),
+            "session=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn request_header_is_parsed() {
+        let cookies = Cookie::parse_header("theme=dark;lang=\"en\";  empty=; novalue; =x; a b=1");
+
+        let pairs: Vec<_> = cookies
+            .iter()
+            .map(|cookie| (cookie.name.as_str(), cookie.value.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![("theme", "dark"), ("lang", "en"), ("empty", "")]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`a;b` is not a valid cookie name")]
+    fn invalid_name_panics() {
+        Cookie::new("a;b", "1");
+    }
+}
@@ -0,0 +1,376 @@
+#[cfg(feature = "secure-cookies")]
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+#[cfg(feature = "secure-cookies")]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+#[cfg(feature = "secure-cookies")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "secure-cookies")]
+use sha2::Sha256;
+
+use super::{cookie::Cookie, request::Request, response::Response};
+
+///
+/// The request cookies with the changes to send back in the response
+///
+/// ex; a handler reads and updates the cookies then calls `apply_to` on its response
+/// to send the changes as `Set-Cookie` headers.
+///
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    /// the cookies received with the request
+    original: Vec<Cookie>,
+
+    /// the cookies added or removed since, in order
+    changes: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn from_request(request: &Request) -> Self {
+        Self {
+            original: request.cookies(),
+            changes: vec![],
+        }
+    }
+
+    ///
+    /// Get a cookie by its name, the last change to it if it was added or removed
+    ///
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        let changed = self.changes.iter().rev().find(|cookie| cookie.name == name);
+        match changed {
+            Some(cookie) if cookie.is_removal() => None,
+            Some(cookie) => Some(cookie),
+            None => self.original.iter().find(|cookie| cookie.name == name),
+        }
+    }
+
+    pub fn add(&mut self, cookie: Cookie) {
+        self.changes.push(cookie);
+    }
+
+    ///
+    /// Remove a cookie from the client, the path and the domain must be the ones it was added with
+    ///
+    pub fn remove(&mut self, cookie: Cookie) {
+        self.changes.push(cookie.removal());
+    }
+
+    /// The cookies added or removed since the jar was created
+    pub fn changes(&self) -> &[Cookie] {
+        &self.changes
+    }
+
+    ///
+    /// Add a `Set-Cookie` header to the response for every change
+    ///
+    pub fn apply_to(&self, response: &mut Response) {
+        for cookie in &self.changes {
+            response.set_cookie(cookie);
+        }
+    }
+
+    ///
+    /// Get a view of the jar where cookie values are signed, see `SignedJar`
+    ///
+    #[cfg(feature = "secure-cookies")]
+    pub fn signed<'a>(&'a mut self, key: &'a Key) -> SignedJar<'a> {
+        SignedJar { jar: self, key }
+    }
+
+    ///
+    /// Get a view of the jar where cookie values are encrypted, see `PrivateJar`
+    ///
+    #[cfg(feature = "secure-cookies")]
+    pub fn private<'a>(&'a mut self, key: &'a Key) -> PrivateJar<'a> {
+        PrivateJar { jar: self, key }
+    }
+}
+
+///
+/// The keys used to sign and encrypt cookies, derived from an app secret
+///
+/// The same secret must be used by all the servers of an app and kept across restarts,
+/// otherwise the cookies they sent can not be read anymore.
+///
+#[cfg(feature = "secure-cookies")]
+#[derive(Clone)]
+pub struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+#[cfg(feature = "secure-cookies")]
+impl Key {
+    ///
+    /// Derive the keys from an app secret
+    ///
+    /// # Panic
+    ///
+    /// If the secret is shorter than 32 bytes
+    ///
+    pub fn from_secret(secret: &[u8]) -> Self {
+        if secret.len() < 32 {
+            panic!("the cookie secret must be at least 32 bytes long");
+        }
+
+        let derive = |purpose: &[u8]| -> [u8; 32] {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret).unwrap();
+            mac.update(purpose);
+            mac.finalize().into_bytes().into()
+        };
+
+        Self {
+            signing: derive(b"signed cookies"),
+            encryption: derive(b"private cookies"),
+        }
+    }
+
+    fn mac(&self, name: &str, value: &str) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.signing).unwrap();
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        mac
+    }
+}
+
+#[cfg(feature = "secure-cookies")]
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// The length of a base64 encoded HMAC-SHA256 signature
+#[cfg(feature = "secure-cookies")]
+const SIGNATURE_SIZE: usize = 43;
+
+/// The length of an AES-GCM nonce in bytes
+#[cfg(feature = "secure-cookies")]
+const NONCE_SIZE: usize = 12;
+
+///
+/// A view of a `CookieJar` where cookie values are signed (HMAC-SHA256)
+///
+/// The client can read the values but can not change them, cookies with a missing
+/// or invalid signature are ignored.
+///
+#[cfg(feature = "secure-cookies")]
+pub struct SignedJar<'a> {
+    jar: &'a mut CookieJar,
+    key: &'a Key,
+}
+
+#[cfg(feature = "secure-cookies")]
+impl SignedJar<'_> {
+    ///
+    /// Get a cookie by its name with its verified value
+    ///
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        let mut cookie = self.jar.get(name)?.clone();
+        let signature = cookie.value.get(..SIGNATURE_SIZE)?;
+        let value = &cookie.value[SIGNATURE_SIZE..];
+
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.key.mac(name, value).verify_slice(&signature).ok()?;
+
+        cookie.value = value.to_owned();
+        Some(cookie)
+    }
+
+    pub fn add(&mut self, mut cookie: Cookie) {
+        let signature = self.key.mac(&cookie.name, &cookie.value).finalize();
+        cookie.value = URL_SAFE_NO_PAD.encode(signature.into_bytes()) + &cookie.value;
+        self.jar.add(cookie);
+    }
+
+    pub fn remove(&mut self, cookie: Cookie) {
+        self.jar.remove(cookie);
+    }
+}
+
+///
+/// A view of a `CookieJar` where cookie values are encrypted (AES-256-GCM)
+///
+/// The client can neither read nor change the values, cookies that can not be
+/// decrypted are ignored. The cookie name is authenticated, so a value can not be
+/// moved to another cookie.
+///
+#[cfg(feature = "secure-cookies")]
+pub struct PrivateJar<'a> {
+    jar: &'a mut CookieJar,
+    key: &'a Key,
+}
+
+#[cfg(feature = "secure-cookies")]
+impl PrivateJar<'_> {
+    ///
+    /// Get a cookie by its name with its decrypted value
+    ///
+    pub fn get(&self, name: &str) -> Option<Cookie> {
+        let mut cookie = self.jar.get(name)?.clone();
+        let sealed = URL_SAFE_NO_PAD.decode(&cookie.value).ok()?;
+        if sealed.len() < NONCE_SIZE {
+            return None;
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: name.as_bytes(),
+        };
+        let value = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .ok()?;
+
+        cookie.value = String::from_utf8(value).ok()?;
+        Some(cookie)
+    }
+
+    pub fn add(&mut self, mut cookie: Cookie) {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: cookie.value.as_bytes(),
+            aad: cookie.name.as_bytes(),
+        };
+        // encrypting only fails if the value is larger than what AES-GCM supports (64GB)
+        let ciphertext = self.cipher().encrypt(&nonce, payload).unwrap();
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        cookie.value = URL_SAFE_NO_PAD.encode(sealed);
+        self.jar.add(cookie);
+    }
+
+    pub fn remove(&mut self, cookie: Cookie) {
+        self.jar.remove(cookie);
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.key.encryption.into())
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    async fn jar(cookie_header: &str) -> CookieJar {
+        let raw = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", cookie_header);
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        request
+            .complete_parsing(&mut reader, None, 1024)
+            .await
+            .unwrap();
+
+        CookieJar::from_request(&request)
+    }
+
+    #[tokio::test]
+    async fn changes_take_precedence_over_request_cookies() {
+        let mut jar = jar("theme=dark; lang=en").await;
+
+        jar.add(Cookie::new("theme", "light"));
+        jar.remove(Cookie::new("lang", ""));
+
+        assert_eq!(jar.get("theme").unwrap().value, "light");
+        assert!(jar.get("lang").is_none());
+        assert_eq!(jar.changes().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn changes_are_sent_as_set_cookie_headers() {
+        let mut jar = jar("").await;
+        jar.add(Cookie::new("a", "1"));
+        jar.add(Cookie::new("b", "2").set_http_only(true));
+
+        let mut response = Response::ok("");
+        jar.apply_to(&mut response);
+
+        let headers: Vec<_> = response
+            .headers
+            .iter()
+            .filter(|header| header.is("Set-Cookie"))
+            .map(|header| header.value.as_str())
+            .collect();
+        assert_eq!(headers, vec!["a=1", "b=2; HttpOnly"]);
+    }
+
+    #[cfg(feature = "secure-cookies")]
+    fn key() -> Key {
+        Key::from_secret(&[7; 32])
+    }
+
+    /// Send the jar changes back as a request `Cookie` header
+    #[cfg(feature = "secure-cookies")]
+    async fn round_trip(jar: &CookieJar) -> CookieJar {
+        let header: Vec<_> = jar
+            .changes()
+            .iter()
+            .map(|cookie| Cookie::new(&cookie.name, &cookie.value).to_string())
+            .collect();
+
+        self::jar(&header.join("; ")).await
+    }
+
+    #[cfg(feature = "secure-cookies")]
+    #[tokio::test]
+    async fn signed_cookies_are_verified() {
+        let key = key();
+        let mut jar = jar("").await;
+        jar.signed(&key).add(Cookie::new("user", "42; admin"));
+
+        let mut received = round_trip(&jar).await;
+        assert_eq!(
+            received.signed(&key).get("user").unwrap().value,
+            "42; admin"
+        );
+        assert!(received.get("user").unwrap().value.ends_with("42; admin"));
+
+        let forged = received.get("user").unwrap().value.replace("42", "43");
+        received.add(Cookie::new("user", &forged));
+        assert!(received.signed(&key).get("user").is_none());
+        assert!(received
+            .signed(&Key::from_secret(&[8; 32]))
+            .get("user")
+            .is_none());
+    }
+
+    #[cfg(feature = "secure-cookies")]
+    #[tokio::test]
+    async fn private_cookies_are_encrypted() {
+        let key = key();
+        let mut jar = jar("").await;
+        jar.private(&key).add(Cookie::new("token", "secret value"));
+
+        let mut received = round_trip(&jar).await;
+        assert!(!received.get("token").unwrap().value.contains("secret"));
+        assert_eq!(
+            received.private(&key).get("token").unwrap().value,
+            "secret value"
+        );
+
+        // a value encrypted for another cookie name is refused
+        let value = received.get("token").unwrap().value.clone();
+        received.add(Cookie::new("other", &value));
+        assert!(received.private(&key).get("other").is_none());
+        assert!(received
+            .private(&Key::from_secret(&[8; 32]))
+            .get("token")
+            .is_none());
+    }
+
+    #[cfg(feature = "secure-cookies")]
+    #[test]
+    #[should_panic(expected = "the cookie secret must be at least 32 bytes long")]
+    fn short_secret_panics() {
+        Key::from_secret(b"secret");
+    }
+}
@@ -1,3 +1,5 @@
+use super::request::RequestParsingError;
+
 #[derive(Debug, Clone)]
 pub struct HttpHeader {
     pub key: String,
@@ -8,4 +10,55 @@ impl HttpHeader {
     pub fn parse(&self) -> String {
         format!("{}: {}\r\n", self.key, self.value)
     }
+
+    ///
+    /// Build a header from a single field line (without the trailing CRLF)
+    ///
+    /// The line must be in the form `field-name ":" OWS field-value OWS` (RFC 9112 section 5),
+    /// where `field-name` is a token, so whitespace before the colon is rejected.
+    ///
+    pub fn from_line(line: &[u8]) -> Result<Self, RequestParsingError> {
+        let colon = line
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or(RequestParsingError::MalformedHeader)?;
+
+        let (name, value) = (&line[..colon], &line[colon + 1..]);
+        if name.is_empty() || !name.iter().all(|b| is_tchar(*b)) {
+            return Err(RequestParsingError::MalformedHeader);
+        }
+
+        // CR, LF and NUL are never allowed inside a field value
+        if value.iter().any(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+            return Err(RequestParsingError::MalformedHeader);
+        }
+
+        Ok(Self {
+            // a token is always ASCII
+            key: String::from_utf8_lossy(name).into_owned(),
+            value: String::from_utf8_lossy(trim_ows(value)).into_owned(),
+        })
+    }
+
+    /// Check if this header has the given name, header names are case-insensitive
+    pub fn is(&self, name: &str) -> bool {
+        self.key.eq_ignore_ascii_case(name)
+    }
+}
+
+/// `tchar` as defined by RFC 9110 section 5.6.2
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Strip optional whitespace (SP / HTAB) from both ends
+fn trim_ows(mut value: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = value {
+        value = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = value {
+        value = rest;
+    }
+
+    value
 }
@@ -1,12 +1,50 @@
-use super::request::RequestParsingError;
+use super::{http_header::is_tchar, request::RequestParsingError};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum HttpMethod {
     Get,
+    Head,
     Post,
     Put,
     Patch,
     Delete,
+    Connect,
+    Options,
+    Trace,
+
+    /// An extension method, ex; WebDAV `PROPFIND`, method names are case-sensitive
+    Other(String),
+}
+
+impl HttpMethod {
+    /// The standard methods (RFC 9110 section 9) and PATCH
+    pub const STANDARD: [HttpMethod; 9] = [
+        HttpMethod::Get,
+        HttpMethod::Head,
+        HttpMethod::Post,
+        HttpMethod::Put,
+        HttpMethod::Patch,
+        HttpMethod::Delete,
+        HttpMethod::Connect,
+        HttpMethod::Options,
+        HttpMethod::Trace,
+    ];
+
+    /// The method name as it's sent in the request line
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Connect => "CONNECT",
+            HttpMethod::Options => "OPTIONS",
+            HttpMethod::Trace => "TRACE",
+            HttpMethod::Other(method) => method,
+        }
+    }
 }
 
 impl TryFrom<&str> for HttpMethod {
@@ -15,11 +53,55 @@ impl TryFrom<&str> for HttpMethod {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "GET" => Ok(HttpMethod::Get),
+            "HEAD" => Ok(HttpMethod::Head),
             "POST" => Ok(HttpMethod::Post),
             "PUT" => Ok(HttpMethod::Put),
             "PATCH" => Ok(HttpMethod::Patch),
             "DELETE" => Ok(HttpMethod::Delete),
+            "CONNECT" => Ok(HttpMethod::Connect),
+            "OPTIONS" => Ok(HttpMethod::Options),
+            "TRACE" => Ok(HttpMethod::Trace),
+            method if !method.is_empty() && method.bytes().all(is_tchar) => {
+                Ok(HttpMethod::Other(method.to_owned()))
+            }
             _ => Err(self::RequestParsingError::InvalidHttpMethod),
         }
     }
 }
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn standard_methods_are_parsed() {
+        for method in HttpMethod::STANDARD {
+            assert_eq!(HttpMethod::try_from(method.as_str()), Ok(method.clone()));
+        }
+    }
+
+    #[test]
+    fn extension_methods_are_parsed() {
+        assert_eq!(
+            HttpMethod::try_from("PROPFIND"),
+            Ok(HttpMethod::Other("PROPFIND".to_owned()))
+        );
+        assert_eq!(HttpMethod::Other("MKCOL".to_owned()).as_str(), "MKCOL");
+    }
+
+    #[test]
+    fn method_names_are_case_sensitive() {
+        assert_eq!(
+            HttpMethod::try_from("get"),
+            Ok(HttpMethod::Other("get".to_owned()))
+        );
+    }
+
+    #[test]
+    fn invalid_methods_are_refused() {
+        assert!(HttpMethod::try_from("").is_err());
+        assert!(HttpMethod::try_from("GE(T").is_err());
+    }
+}
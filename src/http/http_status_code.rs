@@ -0,0 +1,182 @@
+///
+/// Defines the `HttpStatusCode` enum from a list of `Variant => (code, "Reason Phrase")`
+/// together with the code <-> variant conversions.
+///
+macro_rules! status_codes {
+    ($($variant:ident => ($code:literal, $phrase:literal),)+) => {
+        /// Every status code in the IANA HTTP Status Code Registry,
+        /// any other code can be sent using `Custom`
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum HttpStatusCode {
+            $(
+                #[doc = concat!("`", $code, " ", $phrase, "`")]
+                $variant,
+            )+
+
+            /// An unregistered (or application specific) status code with its reason phrase
+            Custom(u16, String),
+        }
+
+        impl HttpStatusCode {
+            pub fn get_code(&self) -> u16 {
+                match self {
+                    $(HttpStatusCode::$variant => $code,)+
+                    HttpStatusCode::Custom(code, _) => *code,
+                }
+            }
+
+            pub fn get_phrase(&self) -> &str {
+                match self {
+                    $(HttpStatusCode::$variant => $phrase,)+
+                    HttpStatusCode::Custom(_, phrase) => phrase,
+                }
+            }
+
+            ///
+            /// Get the status code for a numeric code
+            ///
+            /// Unregistered codes are returned as `Custom` with an empty reason phrase
+            ///
+            pub fn from_code(code: u16) -> Self {
+                match code {
+                    $($code => HttpStatusCode::$variant,)+
+                    _ => HttpStatusCode::Custom(code, String::new()),
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    Continue => (100, "Continue"),
+    SwitchingProtocols => (101, "Switching Protocols"),
+    Processing => (102, "Processing"),
+    EarlyHints => (103, "Early Hints"),
+
+    Ok => (200, "OK"),
+    Created => (201, "Created"),
+    Accepted => (202, "Accepted"),
+    NonAuthoritativeInformation => (203, "Non-Authoritative Information"),
+    NoContent => (204, "No Content"),
+    ResetContent => (205, "Reset Content"),
+    PartialContent => (206, "Partial Content"),
+    MultiStatus => (207, "Multi-Status"),
+    AlreadyReported => (208, "Already Reported"),
+    ImUsed => (226, "IM Used"),
+
+    MultipleChoices => (300, "Multiple Choices"),
+    MovedPermanently => (301, "Moved Permanently"),
+    Found => (302, "Found"),
+    SeeOther => (303, "See Other"),
+    NotModified => (304, "Not Modified"),
+    UseProxy => (305, "Use Proxy"),
+    TemporaryRedirect => (307, "Temporary Redirect"),
+    PermanentRedirect => (308, "Permanent Redirect"),
+
+    BadRequest => (400, "Bad Request"),
+    Unauthorized => (401, "Unauthorized"),
+    PaymentRequired => (402, "Payment Required"),
+    Forbidden => (403, "Forbidden"),
+    NotFound => (404, "Not Found"),
+    MethodNotAllowed => (405, "Method Not Allowed"),
+    NotAcceptable => (406, "Not Acceptable"),
+    ProxyAuthenticationRequired => (407, "Proxy Authentication Required"),
+    RequestTimeout => (408, "Request Timeout"),
+    Conflict => (409, "Conflict"),
+    Gone => (410, "Gone"),
+    LengthRequired => (411, "Length Required"),
+    PreconditionFailed => (412, "Precondition Failed"),
+    ContentTooLarge => (413, "Content Too Large"),
+    UriTooLong => (414, "URI Too Long"),
+    UnsupportedMediaType => (415, "Unsupported Media Type"),
+    RangeNotSatisfiable => (416, "Range Not Satisfiable"),
+    ExpectationFailed => (417, "Expectation Failed"),
+    MisdirectedRequest => (421, "Misdirected Request"),
+    UnprocessableContent => (422, "Unprocessable Content"),
+    Locked => (423, "Locked"),
+    FailedDependency => (424, "Failed Dependency"),
+    TooEarly => (425, "Too Early"),
+    UpgradeRequired => (426, "Upgrade Required"),
+    PreconditionRequired => (428, "Precondition Required"),
+    TooManyRequests => (429, "Too Many Requests"),
+    RequestHeaderFieldsTooLarge => (431, "Request Header Fields Too Large"),
+    UnavailableForLegalReasons => (451, "Unavailable For Legal Reasons"),
+
+    ServerError => (500, "Internal Server Error"),
+    NotImplemented => (501, "Not Implemented"),
+    BadGateway => (502, "Bad Gateway"),
+    ServiceUnavailable => (503, "Service Unavailable"),
+    GatewayTimeout => (504, "Gateway Timeout"),
+    HttpVersionNotSupported => (505, "HTTP Version Not Supported"),
+    VariantAlsoNegotiates => (506, "Variant Also Negotiates"),
+    InsufficientStorage => (507, "Insufficient Storage"),
+    LoopDetected => (508, "Loop Detected"),
+    NotExtended => (510, "Not Extended"),
+    NetworkAuthenticationRequired => (511, "Network Authentication Required"),
+}
+
+impl HttpStatusCode {
+    ///
+    /// Check if a response with this status can have a body
+    ///
+    /// 1xx, 204 and 304 responses never have content (RFC 9110 section 6.4.1)
+    ///
+    pub fn allows_body(&self) -> bool {
+        let code = self.get_code();
+        !((100..200).contains(&code) || code == 204 || code == 304)
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn registered_codes_have_their_phrase() {
+        assert_eq!(HttpStatusCode::Ok.get_phrase(), "OK");
+        assert_eq!(HttpStatusCode::TooManyRequests.get_code(), 429);
+        assert_eq!(
+            HttpStatusCode::ServerError.get_phrase(),
+            "Internal Server Error"
+        );
+    }
+
+    #[test]
+    fn from_code_finds_registered_codes() {
+        assert_eq!(
+            HttpStatusCode::from_code(503),
+            HttpStatusCode::ServiceUnavailable
+        );
+        assert_eq!(
+            HttpStatusCode::from_code(422),
+            HttpStatusCode::UnprocessableContent
+        );
+    }
+
+    #[test]
+    fn from_code_falls_back_to_custom() {
+        let status = HttpStatusCode::from_code(599);
+
+        assert_eq!(status, HttpStatusCode::Custom(599, String::new()));
+        assert_eq!(status.get_code(), 599);
+        assert_eq!(status.get_phrase(), "");
+    }
+
+    #[test]
+    fn custom_code_keeps_its_phrase() {
+        let status = HttpStatusCode::Custom(299, "Fine I Guess".to_owned());
+
+        assert_eq!(status.get_code(), 299);
+        assert_eq!(status.get_phrase(), "Fine I Guess");
+    }
+
+    #[test]
+    fn allows_body_excludes_informational_no_content_and_not_modified() {
+        assert!(!HttpStatusCode::Continue.allows_body());
+        assert!(!HttpStatusCode::NoContent.allows_body());
+        assert!(!HttpStatusCode::NotModified.allows_body());
+        assert!(HttpStatusCode::Ok.allows_body());
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+
+/// The MIME type used when the file extension is unknown
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+///
+/// Guess a file MIME type from its extension
+///
+/// It returns `application/octet-stream` for unknown extensions,
+/// text types are returned with the `utf-8` charset.
+///
+pub fn from_path<P: AsRef<Path>>(path: P) -> &'static str {
+    let extension = match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return DEFAULT_MIME_TYPE,
+    };
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "xml" => "application/xml",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn known_extensions_are_detected() {
+        assert_eq!(from_path("static/index.html"), "text/html; charset=utf-8");
+        assert_eq!(from_path("logo.PNG"), "image/png");
+        assert_eq!(
+            from_path("/a/b/app.min.js"),
+            "text/javascript; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn unknown_extensions_fall_back_to_octet_stream() {
+        assert_eq!(from_path("archive.xyz"), DEFAULT_MIME_TYPE);
+        assert_eq!(from_path("Makefile"), DEFAULT_MIME_TYPE);
+    }
+}
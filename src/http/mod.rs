@@ -1,5 +1,14 @@
+pub mod conditional;
+pub mod cookie;
+pub mod cookie_jar;
 pub mod http_header;
 pub mod http_method;
+pub mod http_status_code;
+pub mod mime;
+pub mod multipart;
+pub mod range;
 pub mod request;
 pub mod request_param;
 pub mod response;
+pub mod response_body;
+pub mod url_encoding;
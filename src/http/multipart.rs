@@ -0,0 +1,750 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt};
+
+use crate::app::extract::ExtractionError;
+
+use super::{http_header::HttpHeader, response::HttpStatusCode};
+
+/// The maximum size of the header section of a single part in bytes
+const MAX_PART_HEADERS_SIZE: usize = 8 * 1024;
+
+/// The maximum length of a boundary (RFC 2046 section 5.1.1)
+const MAX_BOUNDARY_SIZE: usize = 70;
+
+///
+/// The limits applied while parsing a `multipart/form-data` body, see `Server::set_multipart_limits`
+///
+/// Parts larger than `max_memory_size` are spooled to a temporary file in `temp_dir`
+/// instead of being kept in memory, the file is deleted when the part is dropped.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartLimits {
+    max_part_size: usize,
+    max_total_size: usize,
+    max_parts: usize,
+    max_memory_size: usize,
+    temp_dir: PathBuf,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self {
+            max_part_size: 10 * 1024 * 1024,
+            max_total_size: 10 * 1024 * 1024,
+            max_parts: 100,
+            max_memory_size: 256 * 1024,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+impl MultipartLimits {
+    ///
+    /// Set the maximum size of a single part body, default is 10MB
+    ///
+    pub fn set_max_part_size(mut self, max_part_size: usize) -> Self {
+        self.max_part_size = max_part_size;
+        self
+    }
+
+    ///
+    /// Set the maximum size of the whole multipart body, default is 10MB
+    ///
+    /// Multipart bodies are parsed while they are read, so this limit replaces the server
+    /// maximum body size for them.
+    ///
+    pub fn set_max_total_size(mut self, max_total_size: usize) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    pub fn max_total_size(&self) -> usize {
+        self.max_total_size
+    }
+
+    ///
+    /// Set the maximum number of parts, default is 100
+    ///
+    pub fn set_max_parts(mut self, max_parts: usize) -> Self {
+        self.max_parts = max_parts;
+        self
+    }
+
+    ///
+    /// Set the size above which a part is spooled to a temporary file, default is 256KB
+    ///
+    pub fn set_max_memory_size(mut self, max_memory_size: usize) -> Self {
+        self.max_memory_size = max_memory_size;
+        self
+    }
+
+    ///
+    /// Set the directory of the temporary files of spooled parts, default is the system temp directory
+    ///
+    pub fn set_temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultipartError {
+    /// The `Content-Type` is not `multipart/form-data` or has no valid boundary
+    InvalidContentType,
+
+    /// The body is not a valid multipart body, ex; a part without `Content-Disposition` or a missing final boundary
+    Malformed,
+
+    /// A part body is larger than the allowed maximum part size
+    PartTooLarge,
+
+    /// The whole body is larger than the allowed maximum total size
+    BodyTooLarge,
+
+    /// The body has more parts than the allowed maximum
+    TooManyParts,
+
+    /// Reading the body or writing a spooled part to its temporary file failed
+    Io(std::io::ErrorKind),
+}
+
+impl MultipartError {
+    pub fn status_code(&self) -> HttpStatusCode {
+        match self {
+            MultipartError::InvalidContentType => HttpStatusCode::UnsupportedMediaType,
+            MultipartError::Malformed => HttpStatusCode::BadRequest,
+            MultipartError::PartTooLarge
+            | MultipartError::BodyTooLarge
+            | MultipartError::TooManyParts => HttpStatusCode::ContentTooLarge,
+            MultipartError::Io(_) => HttpStatusCode::ServerError,
+        }
+    }
+}
+
+impl Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::InvalidContentType => f.write_str(
+                "Expected a multipart body with `Content-Type: multipart/form-data; boundary=..`",
+            ),
+            MultipartError::Malformed => f.write_str("Malformed multipart body"),
+            MultipartError::PartTooLarge => f.write_str("Multipart part is too large"),
+            MultipartError::BodyTooLarge => f.write_str("Multipart body is too large"),
+            MultipartError::TooManyParts => f.write_str("Multipart body has too many parts"),
+            MultipartError::Io(kind) => write!(f, "Reading a multipart body failed: {}", kind),
+        }
+    }
+}
+
+impl From<std::io::Error> for MultipartError {
+    fn from(error: std::io::Error) -> Self {
+        MultipartError::Io(error.kind())
+    }
+}
+
+impl From<MultipartError> for ExtractionError {
+    fn from(error: MultipartError) -> Self {
+        ExtractionError::new(error.status_code(), &error.to_string())
+    }
+}
+
+///
+/// The body of a part, in memory or spooled to a temporary file
+///
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>),
+    Spooled { file: NamedTempFile, len: usize },
+}
+
+///
+/// A form field or a file part of a `multipart/form-data` body
+///
+#[derive(Debug)]
+pub struct Part {
+    /// list of the part headers, ex; `Content-Disposition` and `Content-Type`
+    pub headers: Vec<HttpHeader>,
+
+    /// the form field name
+    pub name: String,
+
+    /// the name of the uploaded file, None for plain form fields
+    ///
+    /// It is sent by the client, so it must not be used as a path as is.
+    pub filename: Option<String>,
+
+    pub data: PartData,
+}
+
+impl Part {
+    ///
+    /// Get a part header value by its name, header names are case-insensitive
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.is(name))
+            .map(|header| header.value.as_str())
+    }
+
+    /// The part `Content-Type`, if any
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+
+    /// Check if the part is an uploaded file rather than a plain form field
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// The part body size in bytes
+    pub fn len(&self) -> usize {
+        match &self.data {
+            PartData::Memory(bytes) => bytes.len(),
+            PartData::Spooled { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Get the part body, it returns None if the part was spooled to a temporary file
+    ///
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match &self.data {
+            PartData::Memory(bytes) => Some(bytes),
+            PartData::Spooled { .. } => None,
+        }
+    }
+
+    ///
+    /// Get the part body as text
+    ///
+    /// It returns None if the part was spooled or the body is not valid UTF-8
+    ///
+    pub fn text(&self) -> Option<&str> {
+        self.bytes()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    ///
+    /// Get the path of the temporary file of a spooled part
+    ///
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            PartData::Memory(_) => None,
+            PartData::Spooled { file, .. } => Some(file.path()),
+        }
+    }
+
+    ///
+    /// Save the part body to a file, spooled parts are moved instead of copied when possible
+    ///
+    pub async fn save<P: AsRef<Path>>(self, path: P) -> std::io::Result<()> {
+        match self.data {
+            PartData::Memory(bytes) => tokio::fs::write(path, bytes).await,
+            PartData::Spooled { file, .. } => match file.persist(path.as_ref()) {
+                Ok(_) => Ok(()),
+                // the temp directory may be on another file system
+                Err(error) => tokio::fs::copy(error.file.path(), path).await.map(|_| ()),
+            },
+        }
+    }
+}
+
+///
+/// A streaming `multipart/form-data` parser (RFC 7578)
+///
+/// The parts are read one by one from `reader` using `next_part`, so only the current
+/// part is kept, and the parts larger than the memory limit are written to temporary files
+/// as they are read.
+///
+/// ex; `while let Some(part) = multipart.next_part().await? { part.save(..).await? }`
+///
+pub struct Multipart<R> {
+    reader: R,
+    limits: MultipartLimits,
+
+    /// `CRLF "--" boundary`, the delimiter that ends a part body
+    delimiter: Vec<u8>,
+
+    /// the bytes read from `reader` but not parsed yet
+    buffer: Vec<u8>,
+
+    /// the number of bytes read from `reader`
+    total_size: usize,
+    parts_no: usize,
+    started: bool,
+    done: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> Multipart<R> {
+    pub fn new(reader: R, boundary: &str, limits: MultipartLimits) -> Self {
+        Self {
+            reader,
+            limits,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // the first delimiter is usually at the start of the body, without a CRLF before it
+            buffer: b"\r\n".to_vec(),
+            total_size: 0,
+            parts_no: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    ///
+    /// Get the boundary of a `multipart/form-data` content type,
+    /// ex; `multipart/form-data; boundary="----abc"` gives `----abc`
+    ///
+    pub fn boundary(content_type: &str) -> Result<String, MultipartError> {
+        let (media_type, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+        if !media_type
+            .trim()
+            .eq_ignore_ascii_case("multipart/form-data")
+        {
+            return Err(MultipartError::InvalidContentType);
+        }
+
+        let boundary = parse_params(params)
+            .into_iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+            .map(|(_, value)| value)
+            .ok_or(MultipartError::InvalidContentType)?;
+
+        if boundary.is_empty() || boundary.len() > MAX_BOUNDARY_SIZE {
+            return Err(MultipartError::InvalidContentType);
+        }
+
+        Ok(boundary)
+    }
+
+    ///
+    /// Read the next part, it returns None after the last part
+    ///
+    pub async fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        if !self.started {
+            // skip the preamble before the first delimiter
+            self.started = true;
+            self.read_until_delimiter(&mut Sink::Discard).await?;
+        }
+
+        // the last delimiter is followed by `--`, anything after it is ignored
+        while self.buffer.len() < 2 {
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+
+        // a delimiter can be followed by whitespace (transport padding) before the CRLF
+        let padding = self.read_line().await?;
+        if !padding.iter().all(|b| matches!(b, b' ' | b'\t')) {
+            return Err(MultipartError::Malformed);
+        }
+
+        self.parts_no += 1;
+        if self.parts_no > self.limits.max_parts {
+            return Err(MultipartError::TooManyParts);
+        }
+
+        let headers = self.read_headers().await?;
+        let (name, filename) = headers
+            .iter()
+            .find(|header| header.is("Content-Disposition"))
+            .and_then(|header| parse_disposition(&header.value))
+            .ok_or(MultipartError::Malformed)?;
+
+        let mut sink = Sink::Memory(vec![]);
+        self.read_until_delimiter(&mut sink).await?;
+
+        let data = match sink {
+            Sink::Spooled {
+                file,
+                mut writer,
+                len,
+            } => {
+                writer.flush().await?;
+                PartData::Spooled { file, len }
+            }
+            Sink::Memory(bytes) => PartData::Memory(bytes),
+            Sink::Discard => unreachable!(),
+        };
+
+        Ok(Some(Part {
+            headers,
+            name,
+            filename,
+            data,
+        }))
+    }
+
+    ///
+    /// Read more bytes from the reader into the buffer, it returns false at the end of the body
+    ///
+    async fn fill(&mut self) -> Result<bool, MultipartError> {
+        let chunk = self.reader.fill_buf().await?;
+        let len = chunk.len();
+        if len == 0 {
+            return Ok(false);
+        }
+
+        self.total_size += len;
+        if self.total_size > self.limits.max_total_size {
+            return Err(MultipartError::BodyTooLarge);
+        }
+
+        self.buffer.extend_from_slice(chunk);
+        self.reader.consume(len);
+        Ok(true)
+    }
+
+    ///
+    /// Move the bytes before the next delimiter to `sink`, then skip the delimiter
+    ///
+    async fn read_until_delimiter(&mut self, sink: &mut Sink) -> Result<(), MultipartError> {
+        loop {
+            if let Some(position) = find(&self.buffer, &self.delimiter) {
+                sink.write(&self.buffer[..position], &self.limits).await?;
+                self.buffer.drain(..position + self.delimiter.len());
+                return Ok(());
+            }
+
+            // the end of the buffer can be the start of a delimiter split between two reads
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            sink.write(&self.buffer[..safe], &self.limits).await?;
+            self.buffer.drain(..safe);
+
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    /// Read a CRLF terminated line, without the CRLF
+    async fn read_line(&mut self) -> Result<Vec<u8>, MultipartError> {
+        loop {
+            if let Some(position) = find(&self.buffer, b"\r\n") {
+                let line = self.buffer[..position].to_vec();
+                self.buffer.drain(..position + 2);
+                return Ok(line);
+            }
+
+            if self.buffer.len() > MAX_PART_HEADERS_SIZE || !self.fill().await? {
+                return Err(MultipartError::Malformed);
+            }
+        }
+    }
+
+    /// Read the part header section until the empty line that ends it
+    async fn read_headers(&mut self) -> Result<Vec<HttpHeader>, MultipartError> {
+        let mut headers = vec![];
+        let mut size = 0;
+
+        loop {
+            let line = self.read_line().await?;
+            if line.is_empty() {
+                return Ok(headers);
+            }
+
+            size += line.len() + 2;
+            if size > MAX_PART_HEADERS_SIZE {
+                return Err(MultipartError::Malformed);
+            }
+
+            headers.push(HttpHeader::from_line(&line).map_err(|_| MultipartError::Malformed)?);
+        }
+    }
+}
+
+///
+/// Where the bytes of a part body go while it's being read
+///
+enum Sink {
+    /// the preamble before the first part
+    Discard,
+    Memory(Vec<u8>),
+    Spooled {
+        file: NamedTempFile,
+        writer: tokio::fs::File,
+        len: usize,
+    },
+}
+
+impl Sink {
+    async fn write(
+        &mut self,
+        bytes: &[u8],
+        limits: &MultipartLimits,
+    ) -> Result<(), MultipartError> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Sink::Discard => Ok(()),
+            Sink::Memory(memory) => {
+                let len = memory.len() + bytes.len();
+                if len > limits.max_part_size {
+                    return Err(MultipartError::PartTooLarge);
+                }
+                if len <= limits.max_memory_size {
+                    memory.extend_from_slice(bytes);
+                    return Ok(());
+                }
+
+                let file = NamedTempFile::new_in(&limits.temp_dir)?;
+                let mut writer = tokio::fs::File::from_std(file.as_file().try_clone()?);
+                writer.write_all(memory).await?;
+                writer.write_all(bytes).await?;
+
+                *self = Sink::Spooled { file, writer, len };
+                Ok(())
+            }
+            Sink::Spooled { writer, len, .. } => {
+                *len += bytes.len();
+                if *len > limits.max_part_size {
+                    return Err(MultipartError::PartTooLarge);
+                }
+
+                writer.write_all(bytes).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+///
+/// Get the field name and the filename of a `form-data` Content-Disposition,
+/// ex; `form-data; name="avatar"; filename="me.png"`
+///
+fn parse_disposition(value: &str) -> Option<(String, Option<String>)> {
+    let (disposition, params) = value.split_once(';')?;
+    if !disposition.trim().eq_ignore_ascii_case("form-data") {
+        return None;
+    }
+
+    let params = parse_params(params);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_owned())
+    };
+
+    Some((param("name")?, param("filename")))
+}
+
+///
+/// Parse `; key=value` header params, values can be tokens or quoted strings
+///
+/// In quoted strings only `\"` and `\\` are escapes, other backslashes are kept
+/// since browsers do not escape them in filenames.
+///
+fn parse_params(params: &str) -> Vec<(String, String)> {
+    let mut parsed = vec![];
+    let mut chars = params.chars().peekable();
+
+    loop {
+        while matches!(chars.peek(), Some(';' | ' ' | '\t')) {
+            chars.next();
+        }
+
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() {
+            return parsed;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' if matches!(chars.peek(), Some('"' | '\\')) => {
+                        value.extend(chars.next());
+                    }
+                    c => value.push(c),
+                }
+            }
+            // skip anything between the closing quote and the next param
+            chars.by_ref().take_while(|c| *c != ';').for_each(drop);
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ';').collect();
+        }
+
+        parsed.push((key.trim().to_owned(), value.trim_end().to_owned()));
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use tokio::io::BufReader;
+
+    use super::*;
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Hello\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"avatar\"; filename=\"me \\\"1\\\".png\"\r\n\
+        Content-Type: image/png\r\n\
+        \r\n\
+        \u{89}PNG\r\n--not the boundary\r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    async fn parts(body: &[u8], limits: MultipartLimits) -> Result<Vec<Part>, MultipartError> {
+        // a tiny buffer so delimiters are split between reads
+        let reader = BufReader::with_capacity(3, body);
+        let mut multipart = Multipart::new(reader, "XyZ", limits);
+
+        let mut parts = vec![];
+        while let Some(part) = multipart.next_part().await? {
+            parts.push(part);
+        }
+        Ok(parts)
+    }
+
+    #[tokio::test]
+    async fn fields_and_files_are_parsed() {
+        let parts = parts(BODY.as_bytes(), MultipartLimits::default())
+            .await
+            .unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].text(), Some("Hello"));
+
+        assert_eq!(parts[1].name, "avatar");
+        assert_eq!(parts[1].filename.as_deref(), Some("me \"1\".png"));
+        assert_eq!(parts[1].content_type(), Some("image/png"));
+        assert_eq!(
+            parts[1].bytes(),
+            Some("\u{89}PNG\r\n--not the boundary".as_bytes())
+        );
+    }
+
+    #[tokio::test]
+    async fn large_parts_are_spooled_to_the_temp_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = MultipartLimits::default()
+            .set_max_memory_size(5)
+            .set_temp_dir(dir.path());
+
+        let mut parts = parts(BODY.as_bytes(), limits).await.unwrap();
+
+        assert_eq!(parts[0].text(), Some("Hello"));
+        let avatar = parts.pop().unwrap();
+        let path = avatar.path().unwrap().to_owned();
+        assert!(path.starts_with(dir.path()));
+        assert_eq!(avatar.len(), 25);
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            "\u{89}PNG\r\n--not the boundary".as_bytes()
+        );
+
+        let saved = dir.path().join("avatar.png");
+        avatar.save(&saved).await.unwrap();
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&saved).unwrap().len(), 25);
+    }
+
+    #[tokio::test]
+    async fn limits_are_enforced() {
+        let body = BODY.as_bytes();
+
+        let part = parts(body, MultipartLimits::default().set_max_part_size(10)).await;
+        let total = parts(body, MultipartLimits::default().set_max_total_size(100)).await;
+        let count = parts(body, MultipartLimits::default().set_max_parts(1)).await;
+
+        assert_eq!(part.unwrap_err(), MultipartError::PartTooLarge);
+        assert_eq!(total.unwrap_err(), MultipartError::BodyTooLarge);
+        assert_eq!(count.unwrap_err(), MultipartError::TooManyParts);
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_are_rejected() {
+        let missing_end = "--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1";
+        let missing_name = "--XyZ\r\nContent-Disposition: form-data\r\n\r\n1\r\n--XyZ--";
+        let missing_disposition = "--XyZ\r\nContent-Type: text/plain\r\n\r\n1\r\n--XyZ--";
+        let no_boundary = "just some text";
+
+        for body in [missing_end, missing_name, missing_disposition, no_boundary] {
+            let error = parts(body.as_bytes(), MultipartLimits::default()).await;
+            assert_eq!(error.unwrap_err(), MultipartError::Malformed, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_form_has_no_parts() {
+        let parts = parts(b"--XyZ--\r\n", MultipartLimits::default())
+            .await
+            .unwrap();
+
+        assert!(parts.is_empty());
+    }
+
+    #[test]
+    fn boundary_is_taken_from_the_content_type() {
+        let boundary = Multipart::<&[u8]>::boundary;
+
+        assert_eq!(
+            boundary("multipart/form-data; boundary=----abc"),
+            Ok("----abc".to_owned())
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a b;c\""),
+            Ok("a b;c".to_owned())
+        );
+        assert_eq!(
+            boundary("multipart/form-data"),
+            Err(MultipartError::InvalidContentType)
+        );
+        assert_eq!(
+            boundary("application/json; boundary=abc"),
+            Err(MultipartError::InvalidContentType)
+        );
+    }
+
+    #[test]
+    fn errors_have_status_codes() {
+        assert_eq!(
+            MultipartError::Malformed.status_code(),
+            HttpStatusCode::BadRequest
+        );
+        assert_eq!(
+            MultipartError::PartTooLarge.status_code(),
+            HttpStatusCode::ContentTooLarge
+        );
+        assert_eq!(
+            ExtractionError::from(MultipartError::InvalidContentType).status_code,
+            HttpStatusCode::UnsupportedMediaType
+        );
+    }
+}
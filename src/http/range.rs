@@ -0,0 +1,215 @@
+use std::{
+    fs::File,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::response_body::ResponseBody;
+
+/// The max number of ranges in a single request, requests with more ranges are served in full
+const MAX_RANGES: usize = 16;
+
+/// A range of bytes, both bounds are inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The `Content-Range` header value for this range of a `total` bytes representation
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+///
+/// Parse a `Range` header value for a representation of `length` bytes (RFC 9110 section 14.1.2)
+///
+/// It returns None if the header is invalid or not in bytes, then the header must be ignored,
+/// and an empty list if none of the ranges can be satisfied.
+///
+pub fn parse_range(value: &str, length: u64) -> Option<Vec<ByteRange>> {
+    let (unit, specs) = value.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let specs: Vec<_> = specs
+        .split(',')
+        .map(|spec| spec.trim())
+        .filter(|spec| !spec.is_empty())
+        .collect();
+
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return None;
+    }
+
+    let mut ranges = vec![];
+    for spec in specs {
+        let (first, last) = spec.split_once('-')?;
+
+        let range = match (first.trim(), last.trim()) {
+            // the last N bytes
+            ("", suffix) => {
+                let suffix = parse_position(suffix)?;
+                if suffix == 0 || length == 0 {
+                    continue;
+                }
+
+                ByteRange {
+                    start: length.saturating_sub(suffix),
+                    end: length - 1,
+                }
+            }
+
+            // from a position to the end
+            (first, "") => {
+                let first = parse_position(first)?;
+                if first >= length {
+                    continue;
+                }
+
+                ByteRange {
+                    start: first,
+                    end: length - 1,
+                }
+            }
+
+            (first, last) => {
+                let (first, last) = (parse_position(first)?, parse_position(last)?);
+                if last < first {
+                    return None;
+                }
+                if first >= length {
+                    continue;
+                }
+
+                ByteRange {
+                    start: first,
+                    end: last.min(length - 1),
+                }
+            }
+        };
+
+        ranges.push(range);
+    }
+
+    Some(ranges)
+}
+
+fn parse_position(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+///
+/// Build a `multipart/byteranges` body with one part per range of the file (RFC 9110 section 14.6)
+///
+/// `offset` and `length` are the part of the file the ranges are relative to.
+/// It returns the body with the boundary used to separate the parts.
+///
+pub fn multipart_body(
+    file: &File,
+    offset: u64,
+    length: u64,
+    ranges: &[ByteRange],
+    content_type: &str,
+) -> std::io::Result<(ResponseBody, String)> {
+    let boundary = format!(
+        "{:x}{:x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+        std::process::id()
+    );
+
+    let mut parts = vec![];
+    for range in ranges {
+        parts.push(ResponseBody::Bytes(
+            format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary,
+                content_type,
+                range.content_range(length)
+            )
+            .into_bytes(),
+        ));
+        parts.push(ResponseBody::File {
+            file: file.try_clone()?,
+            offset: offset + range.start,
+            length: range.length(),
+        });
+        parts.push(ResponseBody::Bytes(b"\r\n".to_vec()));
+    }
+    parts.push(ResponseBody::Bytes(
+        format!("--{}--\r\n", boundary).into_bytes(),
+    ));
+
+    Ok((ResponseBody::Parts(parts), boundary))
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn single_range_is_parsed() {
+        assert_eq!(parse_range("bytes=0-499", 1000), Some(vec![range(0, 499)]));
+        assert_eq!(parse_range("bytes=500-", 1000), Some(vec![range(500, 999)]));
+        assert_eq!(parse_range("bytes=-200", 1000), Some(vec![range(800, 999)]));
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_length() {
+        assert_eq!(
+            parse_range("bytes=900-2000", 1000),
+            Some(vec![range(900, 999)])
+        );
+        assert_eq!(parse_range("bytes=-2000", 1000), Some(vec![range(0, 999)]));
+    }
+
+    #[test]
+    fn multiple_ranges_are_parsed() {
+        assert_eq!(
+            parse_range("bytes=0-0, -1", 10),
+            Some(vec![range(0, 0), range(9, 9)])
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(
+            parse_range("bytes=5-6, 2000-3000", 1000),
+            Some(vec![range(5, 6)])
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), Some(vec![]));
+    }
+
+    #[test]
+    fn invalid_ranges_are_ignored() {
+        assert_eq!(parse_range("items=0-5", 1000), None);
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=", 1000), None);
+        assert_eq!(
+            parse_range(&format!("bytes={}", "0-1,".repeat(17)), 1000),
+            None
+        );
+    }
+}
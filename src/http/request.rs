@@ -1,22 +1,87 @@
 #![allow(clippy::invalid_regex, dead_code)]
 
-use std::{
-    io::{BufRead, BufReader},
-    net::TcpStream,
-    str::FromStr,
-};
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, BufReader};
+
+use crate::app::{extract::ExtractionError, route::Route, session::Session};
+
+use super::{
+    cookie::Cookie,
+    http_header::HttpHeader,
+    http_method::HttpMethod,
+    multipart::{Multipart, MultipartError, MultipartLimits, Part},
+    request_param::RequestParam,
+    response::HttpStatusCode,
+    url_encoding::parse_urlencoded,
+};
+
+/// The maximum length of the request line in bytes
+const MAX_REQUEST_LINE_SIZE: usize = 8 * 1024;
+
+/// The maximum size of the whole header section in bytes
+const MAX_HEADERS_SIZE: usize = 64 * 1024;
 
-use crate::app::route::Route;
+/// The maximum number of header lines a request can have
+const MAX_HEADERS_NO: usize = 100;
 
-use super::{http_header::HttpHeader, http_method::HttpMethod, request_param::RequestParam};
+/// The maximum length of a chunk-size line (including any chunk extensions)
+const MAX_CHUNK_LINE_SIZE: usize = 1024;
 
-#[derive(Debug, Clone, Copy)]
+/// The size of the pipe between the body reader and the multipart parser
+const MULTIPART_PIPE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestParsingError {
     NonHttpRequest,
     InvalidHttpMethod,
+
+    /// The connection was closed before a full request was received
+    UnexpectedEof,
+
+    /// Reading from the connection failed
+    Io(std::io::ErrorKind),
+
+    /// A header line is not a valid `name: value` field line
+    MalformedHeader,
+
+    /// A header line starts with whitespace (obsolete line folding), see RFC 9112 section 5.2
+    ObsoleteLineFolding,
+
+    /// The request line or the header section exceeded the allowed size
+    HeadersTooLarge,
+
+    /// The Content-Length header is not a valid number, or has conflicting values
+    InvalidContentLength,
+
+    /// The request uses a transfer coding other than `chunked`
+    UnsupportedTransferEncoding,
+
+    /// The chunked body is not correctly encoded
+    InvalidChunk,
+
+    /// The connection was closed before the whole body was received
+    IncompleteBody,
+
+    /// The body is larger than the allowed maximum body size
+    BodyTooLarge,
+
+    /// The `multipart/form-data` body could not be parsed, or is over the multipart limits
+    Multipart(MultipartError),
+}
+
+impl From<std::io::Error> for RequestParsingError {
+    fn from(error: std::io::Error) -> Self {
+        RequestParsingError::Io(error.kind())
+    }
+}
+
+impl From<MultipartError> for RequestParsingError {
+    fn from(error: MultipartError) -> Self {
+        RequestParsingError::Multipart(error)
+    }
 }
 
 #[derive(Debug)]
@@ -32,18 +97,29 @@ pub struct Request {
     /// The request full path, without query parametres
     pub full_path: String,
 
-    /// list of request query parametres
+    /// list of request query parametres, percent-decoded in the order they appear
     pub query_params: Vec<RequestParam>,
 
-    /// list of request route parametres
+    /// list of request route parametres, percent-decoded
     pub route_params: Vec<RequestParam>,
 
     /// list of request headers as (key, value) paires
     pub headers: Vec<HttpHeader>,
 
-    /// The request body
+    /// The request body as raw bytes
     /// it's of type Option becuase some request does not have a body like GET, DELETE
-    pub body: Option<String>,
+    ///
+    /// `multipart/form-data` bodies are not kept here, they are parsed into `parts` as they are read.
+    pub body: Option<Vec<u8>>,
+
+    /// The parts of a `multipart/form-data` body, see `multipart`
+    pub parts: Vec<Part>,
+
+    /// list of trailer fields sent after a chunked body
+    pub trailers: Vec<HttpHeader>,
+
+    /// The request session, it's set by the session middleware, see `App::sessions`
+    pub session: Option<Session>,
 }
 
 impl Request {
@@ -55,14 +131,25 @@ impl Request {
     ///
     pub fn get_query_param<T: FromStr>(&self, name: &str) -> Option<T> {
         match self.query_params.iter().find(|param| param.key == name) {
-            Some(param) => match param.parse::<T>() {
-                Ok(val) => Some(val),
-                Err(_) => None,
-            },
+            Some(param) => param.parse::<T>().ok(),
             None => None,
         }
     }
 
+    ///
+    /// Get all the values of a repeated query paramater in the order they appear, ex; `?tag=a&tag=b`
+    ///
+    /// The values that can not be parsed to the specified type T are skipped,
+    /// it returns an empty list if no param exist with the same name.
+    ///
+    pub fn get_query_params_all<T: FromStr>(&self, name: &str) -> Vec<T> {
+        self.query_params
+            .iter()
+            .filter(|param| param.key == name)
+            .filter_map(|param| param.parse::<T>().ok())
+            .collect()
+    }
+
     ///
     /// Get a route paramater by its name and type
     ///
@@ -71,27 +158,184 @@ impl Request {
     ///
     pub fn get_route_param<T: FromStr>(&self, name: &str) -> Option<T> {
         match self.route_params.iter().find(|param| param.key == name) {
-            Some(param) => match param.parse::<T>() {
-                Ok(val) => Some(val),
-                Err(_) => None,
-            },
+            Some(param) => param.parse::<T>().ok(),
             None => None,
         }
     }
 
+    ///
+    /// Get a header value by its name, header names are case-insensitive
+    ///
+    /// If the header is repeated the first value is returned, use `get_all` to get all of them.
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.is(name))
+            .map(|header| header.value.as_str())
+    }
+
+    ///
+    /// Get all the values of a repeated header in the order they were received
+    ///
+    /// It returns an empty list if the header does not exist
+    ///
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|header| header.is(name))
+            .map(|header| header.value.as_str())
+            .collect()
+    }
+
+    ///
+    /// Get the cookies sent in the `Cookie` header in the order they were received
+    ///
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.get_all("Cookie")
+            .into_iter()
+            .flat_map(Cookie::parse_header)
+            .collect()
+    }
+
+    ///
+    /// Get the request session, it returns None if the app does not use sessions
+    ///
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    ///
+    /// Get a cookie by its name, if the cookie is repeated the first one is returned
+    ///
+    pub fn cookie(&self, name: &str) -> Option<Cookie> {
+        self.cookies()
+            .into_iter()
+            .find(|cookie| cookie.name == name)
+    }
+
+    ///
+    /// Check if the client wants to keep the connection open after this request (RFC 9112 section 9.3)
+    ///
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`,
+    /// HTTP/1.0 connections are persistent only if the client sends `Connection: keep-alive`.
+    ///
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.get_all("Connection")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .any(|value| value.trim().eq_ignore_ascii_case(option))
+        };
+
+        if has_option("close") {
+            return false;
+        }
+
+        match self.http_version.as_str() {
+            "HTTP/1.0" => has_option("keep-alive"),
+            _ => true,
+        }
+    }
+
+    ///
+    /// Get the request body as text
+    ///
+    /// It returns None if the request has no body or the body is not valid UTF-8
+    ///
+    pub fn text(&self) -> Option<&str> {
+        self.body
+            .as_ref()
+            .and_then(|body| std::str::from_utf8(body).ok())
+    }
+
+    ///
+    /// Deserialize the `application/x-www-form-urlencoded` request body, ex; an HTML form submission
+    ///
+    /// It fails with 415 Unsupported Media Type if the `Content-Type` is not a URL-encoded form,
+    /// and with 400 Bad Request if the body can not be deserialized into `T`.
+    ///
+    pub fn form<T: serde::de::DeserializeOwned>(&self) -> Result<T, ExtractionError> {
+        if !self.has_media_type(|media_type| media_type == "application/x-www-form-urlencoded") {
+            return Err(ExtractionError::new(
+                HttpStatusCode::UnsupportedMediaType,
+                "Expected a form body with `Content-Type: application/x-www-form-urlencoded`",
+            ));
+        }
+
+        let body = self.body.as_deref().unwrap_or_default();
+        serde_urlencoded::from_bytes(body)
+            .map_err(|e| ExtractionError::bad_request(&format!("Invalid form body: {}", e)))
+    }
+
+    ///
+    /// Take the parts of the `multipart/form-data` request body, ex; an HTML form with file inputs
+    ///
+    /// The body is parsed while it's read from the connection, so the parts larger than
+    /// the memory limit are already spooled to temporary files, see `Server::set_multipart_limits`.
+    /// The parts are moved out of the request, so calling it again returns no parts.
+    ///
+    /// It fails with `InvalidContentType` if the `Content-Type` is not `multipart/form-data`.
+    ///
+    pub fn multipart(&mut self) -> Result<Vec<Part>, MultipartError> {
+        if !self.has_media_type(|media_type| media_type == "multipart/form-data") {
+            return Err(MultipartError::InvalidContentType);
+        }
+
+        Ok(std::mem::take(&mut self.parts))
+    }
+
+    ///
+    /// Deserialize the JSON request body
+    ///
+    /// It fails with 415 Unsupported Media Type if the `Content-Type` is not JSON
+    /// (`application/json` or any `+json` type), and with 400 Bad Request if the body is not valid JSON.
+    ///
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, ExtractionError> {
+        if !self.has_media_type(|media_type| {
+            media_type == "application/json" || media_type.ends_with("+json")
+        }) {
+            return Err(ExtractionError::new(
+                HttpStatusCode::UnsupportedMediaType,
+                "Expected a JSON body with `Content-Type: application/json`",
+            ));
+        }
+
+        let body = self.body.as_deref().unwrap_or_default();
+        serde_json::from_slice(body)
+            .map_err(|e| ExtractionError::bad_request(&format!("Invalid JSON body: {}", e)))
+    }
+
+    /// Check the `Content-Type` media type (lowercased, without its parameters) with `is_expected`
+    fn has_media_type(&self, is_expected: impl Fn(&str) -> bool) -> bool {
+        self.header("Content-Type")
+            .and_then(|value| value.split(';').next())
+            .map(|media_type| is_expected(&media_type.trim().to_ascii_lowercase()))
+            .unwrap_or(false)
+    }
+
     ///
     /// Parse the request basic information like method, version, base_path..
     ///
     /// This method will not parse request params, headers, or body these information
     /// will be parsed after finding a matching route using `complete_parsing` method.
     ///
-    pub fn initial_parse(stream: &TcpStream) -> Result<Self, RequestParsingError> {
-        let request_line = BufReader::new(stream).lines().next().unwrap().unwrap();
+    pub async fn initial_parse<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<Self, RequestParsingError> {
+        // a server should ignore at least one empty line received before the request line (RFC 9112 section 2.2)
+        let mut request_line = read_line(reader, MAX_REQUEST_LINE_SIZE).await?;
+        if request_line.is_empty() {
+            request_line = read_line(reader, MAX_REQUEST_LINE_SIZE).await?;
+        }
+        let request_line =
+            String::from_utf8(request_line).map_err(|_| RequestParsingError::NonHttpRequest)?;
 
         // this is used to ensure that regular expression is compiled exactly once
         lazy_static! {
             static ref HTTP_REGEX: Regex =
-                Regex::new(r"^(GET|POST|PUT|DELETE|PATCH)\s\/.*\sHTTP\/").unwrap();
+                Regex::new(r"^[!#$%&'*+\-.^_`|~0-9A-Za-z]+\s(\/.*|\*|\S+:\d+)\sHTTP\/").unwrap();
         }
 
         if !HTTP_REGEX.is_match(&request_line) {
@@ -117,6 +361,9 @@ impl Request {
             route_params: vec![],
             headers: vec![],
             body: None,
+            parts: vec![],
+            trailers: vec![],
+            session: None,
         })
     }
 
@@ -124,51 +371,307 @@ impl Request {
     /// This method will parse request params, headers, and body
     /// then append theme to the current request object `self`
     ///
-    pub fn complete_parsing(
+    /// `matched_route` is None when no route matches the request, the rest of the request
+    /// still has to be read so the next request on the same connection can be parsed.
+    ///
+    pub async fn complete_parsing<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        matched_route: Option<&Route>,
+        max_body_size: usize,
+    ) -> Result<(), RequestParsingError> {
+        let limits = MultipartLimits::default();
+        self.complete_parsing_with_limits(reader, matched_route, max_body_size, &limits)
+            .await
+    }
+
+    ///
+    /// Same as `complete_parsing`, with the limits of `multipart/form-data` bodies
+    ///
+    /// The multipart body of a request matching a route is parsed while it's read, it's limited
+    /// by `multipart_limits` instead of `max_body_size` as it's never kept in memory as a whole.
+    ///
+    pub async fn complete_parsing_with_limits<R: AsyncBufRead + Unpin>(
         &mut self,
-        _stream: &TcpStream,
-        matched_route: &Route,
+        reader: &mut R,
+        matched_route: Option<&Route>,
+        max_body_size: usize,
+        multipart_limits: &MultipartLimits,
     ) -> Result<(), RequestParsingError> {
         self.query_params = self.parse_query_params();
-        self.route_params = self.parse_route_params(matched_route);
+        if let Some(route) = matched_route {
+            self.route_params = route.params.clone();
+        }
+        self.headers = Self::parse_headers(reader).await?;
+
+        let multipart = matched_route.is_some()
+            && self.has_media_type(|media_type| media_type == "multipart/form-data");
+        if multipart {
+            self.parse_multipart_body(reader, multipart_limits).await?;
+        } else {
+            self.parse_body(reader, max_body_size).await?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Read the header section line by line until the empty line that ends it
+    ///
+    async fn parse_headers<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+    ) -> Result<Vec<HttpHeader>, RequestParsingError> {
+        let mut headers: Vec<HttpHeader> = vec![];
+        let mut remaining_size = MAX_HEADERS_SIZE;
+
+        loop {
+            let line = read_line(reader, remaining_size).await?;
+            if line.is_empty() {
+                break;
+            }
+
+            // obs-fold is deprecated and a server may reject it with 400 (RFC 9112 section 5.2)
+            if line[0] == b' ' || line[0] == b'\t' {
+                return Err(RequestParsingError::ObsoleteLineFolding);
+            }
+
+            if headers.len() == MAX_HEADERS_NO {
+                return Err(RequestParsingError::HeadersTooLarge);
+            }
+
+            remaining_size = remaining_size.saturating_sub(line.len());
+            headers.push(HttpHeader::from_line(&line)?);
+        }
+
+        // a request with more than one Host header must be rejected (RFC 9112 section 3.2)
+        if headers.iter().filter(|header| header.is("Host")).count() > 1 {
+            return Err(RequestParsingError::MalformedHeader);
+        }
+
+        Ok(headers)
+    }
+
+    ///
+    /// Read the request body according to its framing headers (RFC 9112 section 6.3)
+    ///
+    /// Transfer-Encoding takes precedence, if neither Transfer-Encoding nor Content-Length
+    /// is present the request has no body.
+    ///
+    async fn parse_body<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        max_body_size: usize,
+    ) -> Result<(), RequestParsingError> {
+        if self.is_chunked()? {
+            let mut body = vec![];
+            self.trailers = Self::read_chunked_body(reader, &mut body, max_body_size).await?;
+            self.body = Some(body);
+
+            return Ok(());
+        }
+
+        if let Some(content_length) = self.parse_content_length()? {
+            if content_length > max_body_size {
+                return Err(RequestParsingError::BodyTooLarge);
+            }
 
-        // TODO: add header parsing
+            let mut body = Vec::with_capacity(content_length);
+            (&mut *reader)
+                .take(content_length as u64)
+                .read_to_end(&mut body)
+                .await?;
 
-        // TODO: add body parsing
+            if body.len() != content_length {
+                return Err(RequestParsingError::IncompleteBody);
+            }
+
+            self.body = Some(body);
+        }
 
         Ok(())
     }
 
-    fn parse_route_params(&self, matched_route: &Route) -> Vec<RequestParam> {
-        let params = matched_route.get_params();
-        let param_indexs: Vec<_> = params.iter().map(|param| param.0).collect();
+    ///
+    /// Check if the body is framed with the chunked transfer coding,
+    /// it fails for any other transfer coding
+    ///
+    fn is_chunked(&self) -> Result<bool, RequestParsingError> {
+        let transfer_encoding = self.get_all("Transfer-Encoding");
+        if transfer_encoding.is_empty() {
+            return Ok(false);
+        }
 
-        self.base_path
-            .split('/')
-            .enumerate()
-            .filter(|(i, _)| param_indexs.contains(i))
-            .enumerate()
-            .map(|(i, (_, val))| RequestParam {
-                key: params[i].1.to_owned(),
-                value: val.to_owned(),
-            })
-            .collect()
+        // a message with both headers is a potential request smuggling attempt
+        if self.header("Content-Length").is_some() {
+            return Err(RequestParsingError::InvalidContentLength);
+        }
+
+        let codings: Vec<_> = transfer_encoding
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|coding| coding.trim())
+            .filter(|coding| !coding.is_empty())
+            .collect();
+
+        if codings.len() != 1 || !codings[0].eq_ignore_ascii_case("chunked") {
+            return Err(RequestParsingError::UnsupportedTransferEncoding);
+        }
+
+        Ok(true)
     }
 
-    fn parse_query_params(&self) -> Vec<RequestParam> {
-        let mut query_params = vec![];
-        if let Some((_, query)) = self.full_path.split_once('?') {
-            query_params = query
-                .split('&')
-                .filter_map(|param| param.split_once('='))
-                .map(|(k, v)| RequestParam {
-                    key: k.to_owned(),
-                    value: v.to_owned(),
-                })
-                .collect();
+    ///
+    /// Parse a `multipart/form-data` body into `parts` while it's read
+    ///
+    /// The body is decoded into one end of a pipe while the parts are parsed from the other end,
+    /// so only the part being parsed is kept in memory, and only up to the memory limit.
+    ///
+    async fn parse_multipart_body<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        limits: &MultipartLimits,
+    ) -> Result<(), RequestParsingError> {
+        let content_type = self.header("Content-Type").unwrap_or_default();
+        let boundary = Multipart::<&[u8]>::boundary(content_type)?;
+
+        let chunked = self.is_chunked()?;
+        let content_length = self.parse_content_length()?;
+        if !chunked && content_length.is_none() {
+            return Ok(());
+        }
+        if content_length.is_some_and(|length| length > limits.max_total_size()) {
+            return Err(MultipartError::BodyTooLarge.into());
+        }
+
+        let (mut body_writer, body_reader) = tokio::io::duplex(MULTIPART_PIPE_SIZE);
+
+        // the writer is dropped once the body is read, which ends the body for the parser
+        let read_body = async move {
+            match content_length {
+                Some(length) => {
+                    let read =
+                        tokio::io::copy(&mut (&mut *reader).take(length as u64), &mut body_writer)
+                            .await?;
+                    if read != length as u64 {
+                        return Err(RequestParsingError::IncompleteBody);
+                    }
+
+                    Ok(vec![])
+                }
+                None => {
+                    Self::read_chunked_body(reader, &mut body_writer, limits.max_total_size()).await
+                }
+            }
+        };
+
+        let parse_parts = async move {
+            let mut body_reader = BufReader::new(body_reader);
+            let mut parts = vec![];
+
+            let mut multipart = Multipart::new(&mut body_reader, &boundary, limits.clone());
+            while let Some(part) = multipart.next_part().await? {
+                parts.push(part);
+            }
+
+            // the epilogue after the last part is ignored
+            tokio::io::copy(&mut body_reader, &mut tokio::io::sink()).await?;
+            Ok(parts)
+        };
+
+        let (trailers, parts) = tokio::try_join!(read_body, parse_parts)?;
+        self.trailers = trailers;
+        self.parts = parts;
+
+        Ok(())
+    }
+
+    ///
+    /// Get the Content-Length value if any
+    ///
+    /// Repeated Content-Length headers (or a comma separated list) are accepted
+    /// only if all of the values are the same.
+    ///
+    fn parse_content_length(&self) -> Result<Option<usize>, RequestParsingError> {
+        let mut content_length = None;
+
+        for value in self
+            .get_all("Content-Length")
+            .iter()
+            .flat_map(|value| value.split(','))
+        {
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(RequestParsingError::InvalidContentLength);
+            }
+
+            let length: usize = value
+                .parse()
+                .map_err(|_| RequestParsingError::InvalidContentLength)?;
+
+            if content_length.is_some_and(|current| current != length) {
+                return Err(RequestParsingError::InvalidContentLength);
+            }
+
+            content_length = Some(length);
         }
 
-        query_params
+        Ok(content_length)
+    }
+
+    ///
+    /// Decode a chunked body (RFC 9112 section 7.1) into `body` and return its trailer fields
+    ///
+    /// Chunk extensions are ignored.
+    ///
+    async fn read_chunked_body<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        reader: &mut R,
+        body: &mut W,
+        max_body_size: usize,
+    ) -> Result<Vec<HttpHeader>, RequestParsingError> {
+        let mut body_size = 0;
+
+        loop {
+            let line = read_chunk_line(reader, MAX_CHUNK_LINE_SIZE).await?;
+
+            let size = match line.iter().position(|b| *b == b';') {
+                Some(i) => &line[..i],
+                None => &line[..],
+            };
+            let size = std::str::from_utf8(size)
+                .ok()
+                .map(|size| size.trim_end_matches([' ', '\t']))
+                .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|size| usize::from_str_radix(size, 16).ok())
+                .ok_or(RequestParsingError::InvalidChunk)?;
+
+            if size == 0 {
+                break;
+            }
+
+            if size > max_body_size - body_size {
+                return Err(RequestParsingError::BodyTooLarge);
+            }
+
+            let read = tokio::io::copy(&mut (&mut *reader).take(size as u64), body).await?;
+            if read != size as u64 {
+                return Err(RequestParsingError::IncompleteBody);
+            }
+            body_size += size;
+
+            // every chunk's data is followed by CRLF
+            if !read_chunk_line(reader, 0).await?.is_empty() {
+                return Err(RequestParsingError::InvalidChunk);
+            }
+        }
+
+        Self::parse_headers(reader).await.map_err(into_body_error)
+    }
+
+    fn parse_query_params(&self) -> Vec<RequestParam> {
+        match self.full_path.split_once('?') {
+            Some((_, query)) => parse_urlencoded(query.as_bytes()),
+            None => vec![],
+        }
     }
 
     fn parse_base_path(path: &str) -> String {
@@ -178,3 +681,559 @@ impl Request {
         }
     }
 }
+
+/// Running out of data while reading the body means the body was truncated
+fn into_body_error(error: RequestParsingError) -> RequestParsingError {
+    match error {
+        RequestParsingError::UnexpectedEof => RequestParsingError::IncompleteBody,
+        error => error,
+    }
+}
+
+/// Read a chunk-size line or the CRLF after chunk data, an overlong line is an invalid chunk
+async fn read_chunk_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_size: usize,
+) -> Result<Vec<u8>, RequestParsingError> {
+    read_line(reader, max_size)
+        .await
+        .map_err(|error| match error {
+            RequestParsingError::HeadersTooLarge => RequestParsingError::InvalidChunk,
+            error => into_body_error(error),
+        })
+}
+
+///
+/// Read a single line terminated by LF (or CRLF) and return it without the line terminator
+///
+/// It fails with HeadersTooLarge if the line is longer than `max_size` bytes.
+///
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_size: usize,
+) -> Result<Vec<u8>, RequestParsingError> {
+    let mut line = vec![];
+    let read = (&mut *reader)
+        .take(max_size as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if line.last() != Some(&b'\n') {
+        return Err(if read > max_size {
+            RequestParsingError::HeadersTooLarge
+        } else {
+            RequestParsingError::UnexpectedEof
+        });
+    }
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(line)
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{app::route::IntoRouteHandler, http::response::Response};
+
+    async fn parse(raw: &str) -> Result<Request, RequestParsingError> {
+        parse_with_limit(raw, 1024).await
+    }
+
+    async fn parse_with_limit(
+        raw: &str,
+        max_body_size: usize,
+    ) -> Result<Request, RequestParsingError> {
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        let route = Route::new(HttpMethod::Get, "/".to_owned(), handler);
+
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await?;
+        request
+            .complete_parsing(&mut reader, Some(&route), max_body_size)
+            .await?;
+
+        Ok(request)
+    }
+
+    #[tokio::test]
+    async fn all_methods_and_request_targets_are_parsed() {
+        let request = parse("HEAD /users HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(request.method, HttpMethod::Head);
+
+        let request = parse("OPTIONS * HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(request.method, HttpMethod::Options);
+        assert_eq!(request.base_path, "*");
+
+        let request = parse("CONNECT example.com:443 HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(request.method, HttpMethod::Connect);
+
+        let request = parse("PROPFIND /files HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(request.method, HttpMethod::Other("PROPFIND".to_owned()));
+
+        let result = parse("GE(T / HTTP/1.1\r\n\r\n").await;
+        assert_eq!(result.unwrap_err(), RequestParsingError::NonHttpRequest);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_body_is_deserialized() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 13\r\n\r\n{\"id\": [1,2]}",
+        )
+        .await
+        .unwrap();
+
+        let value: std::collections::HashMap<String, Vec<u32>> = request.json().unwrap();
+
+        assert_eq!(value["id"], vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn query_params_are_percent_decoded() {
+        let request = parse("GET /?q=rust+server&tag=a%26b&debug&tag=c HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            request.get_query_param::<String>("q"),
+            Some("rust server".to_owned())
+        );
+        assert_eq!(
+            request.get_query_param::<String>("debug"),
+            Some("".to_owned())
+        );
+        assert_eq!(
+            request.get_query_params_all::<String>("tag"),
+            vec!["a&b".to_owned(), "c".to_owned()]
+        );
+        assert!(request.get_query_params_all::<u32>("missing").is_empty());
+    }
+
+    #[tokio::test]
+    async fn form_body_is_deserialized() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 25\r\n\r\nname=caf%C3%A9&tags=a+b&x",
+        )
+        .await
+        .unwrap();
+
+        let form: std::collections::HashMap<String, String> = request.form().unwrap();
+
+        assert_eq!(form["name"], "café");
+        assert_eq!(form["tags"], "a b");
+        assert_eq!(form["x"], "");
+    }
+
+    const MULTIPART: &str = "--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"f.txt\"\r\n\r\n0123456789\r\n--b--\r\n";
+
+    #[tokio::test]
+    async fn multipart_body_is_parsed_while_read() {
+        let raw = format!(
+            "POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\nContent-Length: {}\r\n\r\n{}",
+            MULTIPART.len(),
+            MULTIPART
+        );
+
+        // the multipart body is not limited by the max body size
+        let mut request = parse_with_limit(&raw, 4).await.unwrap();
+        assert_eq!(request.body, None);
+
+        let parts = request.multipart().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "a");
+        assert_eq!(parts[0].text(), Some("1"));
+        assert_eq!(parts[1].filename.as_deref(), Some("f.txt"));
+        assert_eq!(parts[1].text(), Some("0123456789"));
+        assert!(request.multipart().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn chunked_multipart_body_is_parsed_and_spooled() {
+        let (head, tail) = MULTIPART.split_at(20);
+        let raw = format!(
+            "POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            head.len(),
+            head,
+            tail.len(),
+            tail
+        );
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        let route = Route::new(HttpMethod::Post, "/".to_owned(), handler);
+        let limits = MultipartLimits::default().set_max_memory_size(4);
+
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        request
+            .complete_parsing_with_limits(&mut reader, Some(&route), 0, &limits)
+            .await
+            .unwrap();
+        assert!(reader.is_empty());
+
+        let parts = request.multipart().unwrap();
+        assert_eq!(parts[0].text(), Some("1"));
+        assert_eq!(
+            std::fs::read(parts[1].path().unwrap()).unwrap(),
+            b"0123456789"
+        );
+    }
+
+    #[tokio::test]
+    async fn multipart_body_over_the_multipart_limits_is_rejected() {
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        let route = Route::new(HttpMethod::Post, "/".to_owned(), handler);
+        let raw = format!(
+            "POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\nContent-Length: {}\r\n\r\n{}",
+            MULTIPART.len(),
+            MULTIPART
+        );
+
+        for (limits, error) in [
+            (
+                MultipartLimits::default().set_max_total_size(16),
+                MultipartError::BodyTooLarge,
+            ),
+            (
+                MultipartLimits::default().set_max_part_size(4),
+                MultipartError::PartTooLarge,
+            ),
+        ] {
+            let mut reader = raw.as_bytes();
+            let mut request = Request::initial_parse(&mut reader).await.unwrap();
+            let result = request
+                .complete_parsing_with_limits(&mut reader, Some(&route), 1024, &limits)
+                .await;
+
+            assert_eq!(result, Err(RequestParsingError::Multipart(error)));
+        }
+    }
+
+    #[tokio::test]
+    async fn multipart_body_needs_a_multipart_content_type() {
+        let mut request =
+            parse("POST / HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\na=1")
+                .await
+                .unwrap();
+
+        assert_eq!(
+            request.multipart().err(),
+            Some(MultipartError::InvalidContentType)
+        );
+    }
+
+    #[tokio::test]
+    async fn form_body_needs_a_form_content_type() {
+        let request =
+            parse("POST / HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\na=1")
+                .await
+                .unwrap();
+
+        let error = request
+            .form::<std::collections::HashMap<String, String>>()
+            .unwrap_err();
+
+        assert_eq!(error.status_code, HttpStatusCode::UnsupportedMediaType);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_body_needs_a_json_content_type() {
+        let request =
+            parse("POST / HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\n{}")
+                .await
+                .unwrap();
+
+        let error = request.json::<serde_json::Value>().unwrap_err();
+        assert_eq!(error.status_code, HttpStatusCode::UnsupportedMediaType);
+
+        let request = parse(
+            "POST / HTTP/1.1\r\nContent-Type: application/problem+json\r\nContent-Length: 2\r\n\r\n{}",
+        )
+        .await
+        .unwrap();
+
+        assert!(request.json::<serde_json::Value>().is_ok());
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn malformed_json_body_is_a_bad_request() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 3\r\n\r\n{,}",
+        )
+        .await
+        .unwrap();
+
+        let error = request.json::<serde_json::Value>().unwrap_err();
+
+        assert_eq!(error.status_code, HttpStatusCode::BadRequest);
+        assert!(error.message.starts_with("Invalid JSON body: "));
+    }
+
+    #[tokio::test]
+    async fn cookies_are_parsed() {
+        let request = parse(
+            "GET / HTTP/1.1\r\nCookie: theme=dark; lang=en\r\nCookie: name=Jane%20Doe\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        let names: Vec<_> = request.cookies().into_iter().map(|c| c.name).collect();
+
+        assert_eq!(names, vec!["theme", "lang", "name"]);
+        assert_eq!(request.cookie("lang").unwrap().value, "en");
+        assert_eq!(request.cookie("name").unwrap().value, "Jane Doe");
+        assert!(request.cookie("missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn headers_are_parsed() {
+        let request = parse("GET / HTTP/1.1\r\nHost: localhost\r\nAccept:  text/html \r\n\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(request.headers.len(), 2);
+        assert_eq!(request.header("Host"), Some("localhost"));
+        assert_eq!(request.header("Accept"), Some("text/html"));
+    }
+
+    #[tokio::test]
+    async fn header_lookup_is_case_insensitive() {
+        let request = parse("GET / HTTP/1.1\r\ncontent-TYPE: text/plain\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(request.header("Content-Type"), Some("text/plain"));
+        assert_eq!(request.header("Authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn repeated_headers_are_kept() {
+        let request =
+            parse("GET / HTTP/1.1\r\nAccept: text/html\r\naccept: application/json\r\n\r\n")
+                .await
+                .unwrap();
+
+        assert_eq!(request.header("Accept"), Some("text/html"));
+        assert_eq!(
+            request.get_all("Accept"),
+            vec!["text/html", "application/json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bare_lf_line_endings_are_accepted() {
+        let request = parse("GET / HTTP/1.1\nHost: localhost\n\n").await.unwrap();
+
+        assert_eq!(request.header("Host"), Some("localhost"));
+    }
+
+    #[tokio::test]
+    async fn obs_fold_is_rejected() {
+        let result = parse("GET / HTTP/1.1\r\nX-Long: first\r\n second\r\n\r\n").await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            RequestParsingError::ObsoleteLineFolding
+        );
+    }
+
+    #[tokio::test]
+    async fn whitespace_before_colon_is_rejected() {
+        let result = parse("GET / HTTP/1.1\r\nHost : localhost\r\n\r\n").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::MalformedHeader);
+    }
+
+    #[tokio::test]
+    async fn line_without_colon_is_rejected() {
+        let result = parse("GET / HTTP/1.1\r\nHost localhost\r\n\r\n").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::MalformedHeader);
+    }
+
+    #[tokio::test]
+    async fn duplicate_host_is_rejected() {
+        let result = parse("GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::MalformedHeader);
+    }
+
+    #[tokio::test]
+    async fn unterminated_header_section_is_rejected() {
+        let result = parse("GET / HTTP/1.1\r\nHost: localhost\r\n").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn too_large_header_section_is_rejected() {
+        let raw = format!(
+            "GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n",
+            "a".repeat(MAX_HEADERS_SIZE)
+        );
+        let result = parse(&raw).await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::HeadersTooLarge);
+    }
+
+    #[tokio::test]
+    async fn request_without_framing_headers_has_no_body() {
+        let request = parse("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert!(request.body.is_none());
+    }
+
+    #[tokio::test]
+    async fn content_length_body_is_read() {
+        let request = parse("POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world")
+            .await
+            .unwrap();
+
+        assert_eq!(request.text(), Some("hello world"));
+    }
+
+    #[tokio::test]
+    async fn binary_body_is_kept_as_bytes() {
+        let mut raw = b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\n".to_vec();
+        raw.extend_from_slice(&[0xff, 0x00, 0xfe]);
+
+        let handler = (|_r: Request| -> Response { todo!() }).into_route_handler();
+        let route = Route::new(HttpMethod::Post, "/".to_owned(), handler);
+        let mut reader = raw.as_slice();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        request
+            .complete_parsing(&mut reader, Some(&route), 1024)
+            .await
+            .unwrap();
+
+        assert_eq!(request.body, Some(vec![0xff, 0x00, 0xfe]));
+        assert_eq!(request.text(), None);
+    }
+
+    #[tokio::test]
+    async fn truncated_body_is_rejected() {
+        let result = parse("POST / HTTP/1.1\r\nContent-Length: 20\r\n\r\nhello").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::IncompleteBody);
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_rejected() {
+        let result = parse_with_limit(
+            "POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world",
+            5,
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::BodyTooLarge);
+    }
+
+    #[tokio::test]
+    async fn conflicting_content_lengths_are_rejected() {
+        let result =
+            parse("POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!").await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            RequestParsingError::InvalidContentLength
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_body_is_decoded() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+        ).await
+        .unwrap();
+
+        assert_eq!(request.text(), Some("hello world"));
+        assert!(request.trailers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn chunked_trailers_are_parsed() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\nChecksum: 123\r\n\r\n",
+        ).await
+        .unwrap();
+
+        assert_eq!(request.text(), Some("abc"));
+        assert_eq!(request.trailers.len(), 1);
+        assert_eq!(request.trailers[0].value, "123");
+    }
+
+    #[tokio::test]
+    async fn truncated_chunked_body_is_rejected() {
+        let result = parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\na\r\nabc").await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::IncompleteBody);
+    }
+
+    #[tokio::test]
+    async fn oversized_chunked_body_is_rejected() {
+        let result = parse_with_limit(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n",
+            5,
+        ).await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::BodyTooLarge);
+    }
+
+    #[tokio::test]
+    async fn invalid_chunk_size_is_rejected() {
+        let result =
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nabc\r\n0\r\n\r\n")
+                .await;
+
+        assert_eq!(result.unwrap_err(), RequestParsingError::InvalidChunk);
+    }
+
+    #[tokio::test]
+    async fn unsupported_transfer_encoding_is_rejected() {
+        let result = parse("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n").await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            RequestParsingError::UnsupportedTransferEncoding
+        );
+    }
+
+    #[tokio::test]
+    async fn http_1_1_connections_are_persistent_by_default() {
+        let request = parse("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert!(request.keep_alive());
+    }
+
+    #[tokio::test]
+    async fn connection_close_is_honoured() {
+        let request = parse("GET / HTTP/1.1\r\nConnection: Upgrade, Close\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert!(!request.keep_alive());
+    }
+
+    #[tokio::test]
+    async fn http_1_0_connections_need_keep_alive() {
+        let request = parse("GET / HTTP/1.0\r\n\r\n").await.unwrap();
+        assert!(!request.keep_alive());
+
+        let request = parse("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(request.keep_alive());
+    }
+}
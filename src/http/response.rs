@@ -1,50 +1,133 @@
 #![allow(dead_code)]
 
-use super::http_header::HttpHeader;
+use std::{fs::File, path::Path};
 
-pub enum HttpStatusCode {
-    Ok,
-    NotFound,
-    ServerError,
-}
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-impl HttpStatusCode {
-    fn get_code(&self) -> usize {
-        match &self {
-            HttpStatusCode::Ok => 200,
-            HttpStatusCode::NotFound => 404,
-            HttpStatusCode::ServerError => 500,
-        }
-    }
+use super::{
+    conditional, cookie::Cookie, http_header::HttpHeader, http_method::HttpMethod, mime, range,
+    request::Request, response_body::ResponseBody,
+};
 
-    fn get_phrase(&self) -> &str {
-        match &self {
-            HttpStatusCode::Ok => "OK",
-            HttpStatusCode::NotFound => "NOT FOUND",
-            HttpStatusCode::ServerError => "INTERNAL SERVER ERROR",
-        }
-    }
-}
+pub use super::http_status_code::HttpStatusCode;
 
 pub struct Response {
     pub http_version: String,
     pub status_code: HttpStatusCode,
     pub headers: Vec<HttpHeader>,
-    pub body: String,
+    pub body: ResponseBody,
 }
 
 impl Response {
+    ///
+    /// Create a response with any status code and a text body
+    ///
+    pub fn new(status_code: HttpStatusCode, body: &str) -> Self {
+        Response {
+            http_version: "1.1".to_owned(),
+            status_code,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    ///
+    /// Replace the response body, it can be bytes, a file or a stream
+    ///
+    pub fn with_body<B: Into<ResponseBody>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    ///
+    /// Create a 200 OK response with the value serialized as JSON
+    ///
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Self {
+        Self::json_with_status(HttpStatusCode::Ok, value)
+    }
+
+    ///
+    /// Create a response with any status code and the value serialized as JSON
+    ///
+    /// Serialization only fails for values JSON can not represent (ex; maps with non-string keys),
+    /// the response is then 500 Internal Server Error.
+    ///
+    #[cfg(feature = "json")]
+    pub fn json_with_status<T: serde::Serialize + ?Sized>(
+        status_code: HttpStatusCode,
+        value: &T,
+    ) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                let mut response = Self::new(status_code, "").with_body(body);
+                response.set_header("Content-Type", "application/json");
+                response
+            }
+            Err(e) => {
+                eprintln!("Failed to serialize the JSON response: {}", e);
+                Self::server_error()
+            }
+        }
+    }
+
     pub fn ok_from_file(path: &str) -> std::io::Result<Self> {
         Self::from_file(path, HttpStatusCode::Ok)
     }
 
     pub fn ok(body: &str) -> Self {
-        Response {
-            http_version: "1.1".to_owned(),
-            status_code: HttpStatusCode::Ok,
-            headers: vec![],
-            body: body.to_owned(),
-        }
+        Self::new(HttpStatusCode::Ok, body)
+    }
+
+    pub fn created(body: &str) -> Self {
+        Self::new(HttpStatusCode::Created, body)
+    }
+
+    pub fn accepted(body: &str) -> Self {
+        Self::new(HttpStatusCode::Accepted, body)
+    }
+
+    pub fn no_content() -> Self {
+        Self::new(HttpStatusCode::NoContent, "")
+    }
+
+    ///
+    /// Redirect the client to `location` using 302 Found
+    ///
+    pub fn redirect(location: &str) -> Self {
+        Self::redirect_with(HttpStatusCode::Found, location)
+    }
+
+    pub fn moved_permanently(location: &str) -> Self {
+        Self::redirect_with(HttpStatusCode::MovedPermanently, location)
+    }
+
+    pub fn see_other(location: &str) -> Self {
+        Self::redirect_with(HttpStatusCode::SeeOther, location)
+    }
+
+    pub fn temporary_redirect(location: &str) -> Self {
+        Self::redirect_with(HttpStatusCode::TemporaryRedirect, location)
+    }
+
+    pub fn permanent_redirect(location: &str) -> Self {
+        Self::redirect_with(HttpStatusCode::PermanentRedirect, location)
+    }
+
+    pub fn not_modified() -> Self {
+        Self::new(HttpStatusCode::NotModified, "")
+    }
+
+    pub fn bad_request() -> Self {
+        Self::new(HttpStatusCode::BadRequest, "")
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(HttpStatusCode::Unauthorized, "")
+    }
+
+    pub fn forbidden() -> Self {
+        Self::new(HttpStatusCode::Forbidden, "")
     }
 
     pub fn not_found_from_file(path: &str) -> std::io::Result<Self> {
@@ -52,49 +135,635 @@ impl Response {
     }
 
     pub fn not_found() -> Self {
-        Response {
-            http_version: "1.1".to_owned(),
-            status_code: HttpStatusCode::NotFound,
-            headers: vec![],
-            body: String::new(),
+        Self::new(HttpStatusCode::NotFound, "")
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::new(HttpStatusCode::MethodNotAllowed, "")
+    }
+
+    pub fn conflict() -> Self {
+        Self::new(HttpStatusCode::Conflict, "")
+    }
+
+    pub fn unprocessable_content() -> Self {
+        Self::new(HttpStatusCode::UnprocessableContent, "")
+    }
+
+    pub fn too_many_requests() -> Self {
+        Self::new(HttpStatusCode::TooManyRequests, "")
+    }
+
+    pub fn server_error() -> Self {
+        Self::new(HttpStatusCode::ServerError, "")
+    }
+
+    pub fn service_unavailable() -> Self {
+        Self::new(HttpStatusCode::ServiceUnavailable, "")
+    }
+
+    fn redirect_with(status_code: HttpStatusCode, location: &str) -> Self {
+        let mut response = Self::new(status_code, "");
+        response.set_header("Location", location);
+        response
+    }
+
+    ///
+    /// Create a response that streams the file content,
+    /// the Content-Type is guessed from the file extension
+    ///
+    /// The `ETag` and `Last-Modified` validators are set from the file metadata,
+    /// use `conditional` to answer with 304 when the client cache is still fresh.
+    ///
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        status_code: HttpStatusCode,
+    ) -> std::io::Result<Self> {
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+
+        let mut response = Self::new(status_code, "").with_body(ResponseBody::file(file)?);
+        response.set_header("Content-Type", mime::from_path(path));
+        response.set_header("Accept-Ranges", "bytes");
+        response.set_header("ETag", &conditional::file_etag(&metadata));
+        if let Ok(modified) = metadata.modified() {
+            response.set_header("Last-Modified", &httpdate::fmt_http_date(modified));
         }
+
+        Ok(response)
     }
 
-    fn from_file(path: &str, status_code: HttpStatusCode) -> std::io::Result<Self> {
-        let file_content = std::fs::read_to_string(path)?;
-        let content_lendth = file_content.len();
+    ///
+    /// Answer with 304 Not Modified if the client cached copy is still fresh (RFC 9110 section 13.2.2)
+    ///
+    /// `If-None-Match` is checked against the response `ETag`, and only if it's absent
+    /// `If-Modified-Since` is checked against `Last-Modified`.
+    /// It only applies to 200 responses of GET and HEAD requests, any other response is returned as is.
+    ///
+    pub fn conditional(self, request: &Request) -> Self {
+        let method = &request.method;
+        if !matches!(method, HttpMethod::Get | HttpMethod::Head)
+            || self.status_code != HttpStatusCode::Ok
+        {
+            return self;
+        }
+
+        let if_none_match = request.get_all("If-None-Match");
+        let fresh = if !if_none_match.is_empty() {
+            self.get_header("ETag")
+                .is_some_and(|etag| conditional::etag_matches_any(&if_none_match.join(","), etag))
+        } else if let Some(since) = request.header("If-Modified-Since") {
+            self.get_header("Last-Modified")
+                .is_some_and(|last_modified| conditional::not_modified_since(last_modified, since))
+        } else {
+            false
+        };
 
-        let mut headers: Vec<HttpHeader> = vec![];
-        headers.push(HttpHeader {
-            key: "Content-Length".to_owned(),
-            value: format!("{content_lendth}"),
-        });
+        if !fresh {
+            return self;
+        }
+
+        // a 304 response has the headers a 200 response would have had to update the cached copy
+        let mut response = Self::not_modified();
+        for key in [
+            "Cache-Control",
+            "Content-Location",
+            "ETag",
+            "Expires",
+            "Last-Modified",
+            "Vary",
+        ] {
+            if let Some(value) = self.get_header(key) {
+                response.set_header(key, value);
+            }
+        }
+
+        response
+    }
+
+    ///
+    /// Answer with only the parts of the file the request `Range` header asks for (RFC 9110 section 14)
+    ///
+    /// - a single range is answered with 206 Partial Content and the `Content-Range` header
+    /// - multiple ranges are answered with 206 and a `multipart/byteranges` body
+    /// - if none of the ranges can be satisfied it's answered with 416 Range Not Satisfiable
+    ///
+    /// The whole file is served if `If-Range` does not match the file validators, or the `Range`
+    /// header is invalid. It only applies to 200 file responses of GET requests.
+    ///
+    pub fn ranged(mut self, request: &Request) -> Self {
+        if request.method != HttpMethod::Get || self.status_code != HttpStatusCode::Ok {
+            return self;
+        }
+
+        let (
+            Some(range),
+            ResponseBody::File {
+                file,
+                offset,
+                length,
+            },
+        ) = (request.header("Range"), &self.body)
+        else {
+            return self;
+        };
+        let (offset, length) = (*offset, *length);
+
+        if let Some(if_range) = request.header("If-Range") {
+            if !conditional::if_range_matches(
+                if_range,
+                self.get_header("ETag"),
+                self.get_header("Last-Modified"),
+            ) {
+                return self;
+            }
+        }
+
+        let Some(ranges) = range::parse_range(range, length) else {
+            return self;
+        };
+
+        if ranges.is_empty() {
+            let mut response = Self::new(HttpStatusCode::RangeNotSatisfiable, "");
+            response.set_header("Content-Range", &format!("bytes */{}", length));
+            return response;
+        }
+
+        if let [range] = ranges[..] {
+            let ResponseBody::File { file, .. } =
+                std::mem::replace(&mut self.body, ResponseBody::empty())
+            else {
+                unreachable!("the body was checked to be a file");
+            };
+
+            self.body = ResponseBody::File {
+                file,
+                offset: offset + range.start,
+                length: range.length(),
+            };
+            self.set_header("Content-Range", &range.content_range(length));
+        } else {
+            let content_type = self
+                .get_header("Content-Type")
+                .unwrap_or(mime::DEFAULT_MIME_TYPE);
+
+            // failing to share the file between the parts is not a reason to fail the request
+            let Ok((body, boundary)) =
+                range::multipart_body(file, offset, length, &ranges, content_type)
+            else {
+                return self;
+            };
 
-        headers.push(HttpHeader {
-            key: "Content-Type".to_owned(),
-            value: String::from("text/html"),
+            self.body = body;
+            self.set_header(
+                "Content-Type",
+                &format!("multipart/byteranges; boundary={}", boundary),
+            );
+        }
+
+        self.status_code = HttpStatusCode::PartialContent;
+        self
+    }
+}
+
+impl Response {
+    /// Get a header value by its name, header names are case-insensitive
+    pub fn get_header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.is(key))
+            .map(|header| header.value.as_str())
+    }
+
+    /// Set a header, replacing any existing header with the same name
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        self.headers.retain(|header| !header.is(key));
+        self.headers.push(HttpHeader {
+            key: key.to_owned(),
+            value: value.to_owned(),
         });
+    }
 
-        Ok(Response {
-            http_version: "1.1".to_owned(),
-            status_code,
-            headers,
-            body: file_content,
-        })
+    ///
+    /// Add a `Set-Cookie` header, the existing cookies are kept
+    ///
+    /// Use `Cookie::removal` to remove a cookie from the client.
+    ///
+    pub fn set_cookie(&mut self, cookie: &Cookie) {
+        self.headers.push(HttpHeader {
+            key: "Set-Cookie".to_owned(),
+            value: cookie.to_string(),
+        });
     }
 }
 
 impl Response {
-    pub fn as_string(&self) -> String {
-        let headers: String = self.headers.iter().map(|header| header.parse()).collect();
+    ///
+    /// Write the response to the connection
+    ///
+    /// The body length is sent in the `Content-Length` header when it's known,
+    /// otherwise the body is sent using the chunked transfer coding if `chunked` is true
+    /// (the client supports HTTP/1.1), or as is until the connection is closed.
+    ///
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        self,
+        writer: &mut W,
+        chunked: bool,
+    ) -> std::io::Result<()> {
+        self.write(writer, chunked, true).await
+    }
+
+    ///
+    /// Write the response to the connection without its body, this is the answer to HEAD requests
+    ///
+    /// The headers are the same as if the body was sent, including `Content-Length`.
+    ///
+    pub async fn write_head_to<W: AsyncWrite + Unpin>(
+        self,
+        writer: &mut W,
+        chunked: bool,
+    ) -> std::io::Result<()> {
+        self.write(writer, chunked, false).await
+    }
 
-        format!(
-            "HTTP/{} {} {}\r\n{}\r\n{}",
+    async fn write<W: AsyncWrite + Unpin>(
+        mut self,
+        writer: &mut W,
+        chunked: bool,
+        with_body: bool,
+    ) -> std::io::Result<()> {
+        if !self.status_code.allows_body() {
+            self.body = ResponseBody::empty();
+        } else if let Some(length) = self.body.len() {
+            // the client needs the body length to find where the next response starts on a persistent connection
+            self.headers
+                .retain(|header| !header.is("Transfer-Encoding"));
+            self.set_header("Content-Length", &length.to_string());
+        } else {
+            self.headers.retain(|header| !header.is("Content-Length"));
+            if chunked {
+                self.set_header("Transfer-Encoding", "chunked");
+            }
+        }
+
+        let headers: String = self.headers.iter().map(|header| header.parse()).collect();
+        let mut head = format!(
+            "HTTP/{} {} {}\r\n{}\r\n",
             self.http_version,
             self.status_code.get_code(),
             self.status_code.get_phrase(),
             headers,
-            self.body
         )
+        .into_bytes();
+
+        if !with_body {
+            writer.write_all(&head).await?;
+            return writer.flush().await;
+        }
+
+        // small bodies are sent with the head in a single write
+        if let ResponseBody::Bytes(bytes) = &self.body {
+            head.extend_from_slice(bytes);
+            writer.write_all(&head).await?;
+            return writer.flush().await;
+        }
+
+        writer.write_all(&head).await?;
+        self.body.write_to(writer, chunked).await
+    }
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use futures_util::stream;
+
+    use super::*;
+
+    async fn write(response: Response, chunked: bool) -> String {
+        let mut output = vec![];
+        response.write_to(&mut output, chunked).await.unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[tokio::test]
+    async fn head_response_keeps_the_headers_without_the_body() {
+        let mut output = vec![];
+        Response::ok("Hello")
+            .write_head_to(&mut output, true)
+            .await
+            .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Content-Length: 5\r\n"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_response_has_content_type_and_length() {
+        let response = Response::json_with_status(
+            HttpStatusCode::Created,
+            &std::collections::BTreeMap::from([("id", 1)]),
+        );
+
+        let output = write(response, true).await;
+
+        assert!(output.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(output.contains("Content-Type: application/json\r\n"));
+        assert!(output.contains("Content-Length: 8\r\n"));
+        assert!(output.ends_with("\r\n\r\n{\"id\":1}"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn unserializable_json_response_is_a_server_error() {
+        let value = std::collections::HashMap::from([((1, 2), "tuple keys")]);
+
+        let response = Response::json(&value);
+
+        assert_eq!(response.status_code, HttpStatusCode::ServerError);
+    }
+
+    #[tokio::test]
+    async fn status_line_uses_code_and_phrase() {
+        let response = Response::new(HttpStatusCode::UnprocessableContent, "invalid");
+
+        assert!(write(response, true)
+            .await
+            .starts_with("HTTP/1.1 422 Unprocessable Content\r\n"));
+    }
+
+    #[tokio::test]
+    async fn custom_status_is_written() {
+        let response = Response::new(
+            HttpStatusCode::Custom(599, "Network Timeout".to_owned()),
+            "",
+        );
+
+        assert!(write(response, true)
+            .await
+            .starts_with("HTTP/1.1 599 Network Timeout\r\n"));
+    }
+
+    #[test]
+    fn redirect_sets_location() {
+        let response = Response::redirect("/login");
+
+        assert_eq!(response.status_code, HttpStatusCode::Found);
+        assert_eq!(response.get_header("location"), Some("/login"));
+    }
+
+    #[tokio::test]
+    async fn no_content_has_no_content_length() {
+        let response = write(Response::no_content(), true).await;
+
+        assert_eq!(response, "HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn content_length_is_added() {
+        let response = write(Response::created("done"), true).await;
+
+        assert!(response.contains("Content-Length: 4\r\n"));
+        assert!(response.ends_with("\r\n\r\ndone"));
+    }
+
+    #[tokio::test]
+    async fn binary_body_is_written_as_is() {
+        let response = Response::ok("").with_body(vec![0xff, 0x00, 0xfe]);
+
+        let mut output = vec![];
+        response.write_to(&mut output, true).await.unwrap();
+
+        assert!(output.ends_with(b"Content-Length: 3\r\n\r\n\xff\x00\xfe"));
+    }
+
+    #[tokio::test]
+    async fn file_body_is_streamed_with_content_length() {
+        let expected = std::fs::read_to_string("static/index.html").unwrap();
+
+        let response = write(Response::ok_from_file("static/index.html").unwrap(), true).await;
+
+        assert!(response.contains(&format!("Content-Length: {}\r\n", expected.len())));
+        assert!(response.ends_with(&expected));
+    }
+
+    #[tokio::test]
+    async fn stream_body_is_chunked() {
+        let chunks = vec![Ok(b"hello".to_vec()), Ok(vec![]), Ok(b" world!".to_vec())];
+        let response = Response::ok("").with_body(ResponseBody::stream(stream::iter(chunks)));
+
+        let response = write(response, true).await;
+
+        assert!(response.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!response.contains("Content-Length"));
+        assert!(response.ends_with("\r\n\r\n5\r\nhello\r\n7\r\n world!\r\n0\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn stream_body_is_written_raw_without_chunked() {
+        let chunks = vec![Ok(b"hello".to_vec()), Ok(b" world!".to_vec())];
+        let response = Response::ok("").with_body(ResponseBody::stream(stream::iter(chunks)));
+
+        let response = write(response, false).await;
+
+        assert!(!response.contains("Transfer-Encoding"));
+        assert!(response.ends_with("\r\n\r\nhello world!"));
+    }
+
+    async fn get(headers: &str) -> Request {
+        request("GET", headers).await
+    }
+
+    async fn request(method: &str, headers: &str) -> Request {
+        let raw = format!("{} / HTTP/1.1\r\n{}\r\n", method, headers);
+        let mut reader = raw.as_bytes();
+        let mut request = Request::initial_parse(&mut reader).await.unwrap();
+        request
+            .complete_parsing(&mut reader, None, 0)
+            .await
+            .unwrap();
+
+        request
+    }
+
+    #[test]
+    fn file_response_has_validators() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+
+        assert!(response.get_header("ETag").is_some());
+        assert!(response.get_header("Last-Modified").is_some());
+    }
+
+    #[tokio::test]
+    async fn matching_etag_is_not_modified() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+        let etag = response.get_header("ETag").unwrap().to_owned();
+
+        let request = get(&format!("If-None-Match: \"other\", {}\r\n", etag)).await;
+        let response = response.conditional(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::NotModified);
+        assert_eq!(response.get_header("ETag"), Some(etag.as_str()));
+        assert!(response.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn head_request_with_matching_validators_is_not_modified() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+        let etag = response.get_header("ETag").unwrap().to_owned();
+        let last_modified = response.get_header("Last-Modified").unwrap().to_owned();
+
+        let if_none_match = request("HEAD", &format!("If-None-Match: {}\r\n", etag)).await;
+        let if_modified_since =
+            request("HEAD", &format!("If-Modified-Since: {}\r\n", last_modified)).await;
+        let post = request("POST", &format!("If-None-Match: {}\r\n", etag)).await;
+
+        for (request, status_code) in [
+            (if_none_match, HttpStatusCode::NotModified),
+            (if_modified_since, HttpStatusCode::NotModified),
+            (post, HttpStatusCode::Ok),
+        ] {
+            let response = Response::ok_from_file("static/index.html").unwrap();
+            assert_eq!(response.conditional(&request).status_code, status_code);
+        }
+    }
+
+    #[tokio::test]
+    async fn other_etag_is_served() {
+        let request = get("If-None-Match: \"other\"\r\n").await;
+
+        let response = Response::ok_from_file("static/index.html")
+            .unwrap()
+            .conditional(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+    }
+
+    #[tokio::test]
+    async fn not_modified_since_is_not_modified() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+        let last_modified = response.get_header("Last-Modified").unwrap().to_owned();
+
+        let request = get(&format!("If-Modified-Since: {}\r\n", last_modified)).await;
+
+        assert_eq!(
+            response.conditional(&request).status_code,
+            HttpStatusCode::NotModified
+        );
+    }
+
+    #[tokio::test]
+    async fn if_none_match_takes_precedence_over_if_modified_since() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+        let last_modified = response.get_header("Last-Modified").unwrap().to_owned();
+
+        let request = get(&format!(
+            "If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n",
+            last_modified
+        ))
+        .await;
+
+        assert_eq!(
+            response.conditional(&request).status_code,
+            HttpStatusCode::Ok
+        );
+    }
+
+    async fn write_bytes(response: Response) -> Vec<u8> {
+        let mut output = vec![];
+        response.write_to(&mut output, true).await.unwrap();
+
+        output
+    }
+
+    #[tokio::test]
+    async fn single_range_is_partial_content() {
+        let content = std::fs::read("static/index.html").unwrap();
+        let request = get("Range: bytes=0-14\r\n").await;
+
+        let response = Response::ok_from_file("static/index.html")
+            .unwrap()
+            .ranged(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::PartialContent);
+        assert_eq!(
+            response.get_header("Content-Range"),
+            Some(format!("bytes 0-14/{}", content.len()).as_str())
+        );
+        let output = write_bytes(response).await;
+        assert!(output.ends_with(b"Content-Length: 15\r\n\r\n<!DOCTYPE html>"));
+    }
+
+    #[tokio::test]
+    async fn multiple_ranges_are_multipart() {
+        let content = std::fs::read("static/index.html").unwrap();
+        let request = get("Range: bytes=0-1, -2\r\n").await;
+
+        let response = Response::ok_from_file("static/index.html")
+            .unwrap()
+            .ranged(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::PartialContent);
+        let content_type = response.get_header("Content-Type").unwrap().to_owned();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_owned();
+
+        let output = String::from_utf8(write_bytes(response).await).unwrap();
+        let (head, body) = output.split_once("\r\n\r\n").unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Range: bytes 0-1/{len}\r\n\r\n<!\r\n\
+             --{b}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Range: bytes {start}-{end}/{len}\r\n\r\nl>\r\n\
+             --{b}--\r\n",
+            b = boundary,
+            len = content.len(),
+            start = content.len() - 2,
+            end = content.len() - 1,
+        );
+        assert_eq!(body, expected);
+        assert!(head.contains(&format!("Content-Length: {}", expected.len())));
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_range_is_416() {
+        let request = get("Range: bytes=100000-\r\n").await;
+
+        let response = Response::ok_from_file("static/index.html")
+            .unwrap()
+            .ranged(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::RangeNotSatisfiable);
+        assert!(response
+            .get_header("Content-Range")
+            .unwrap()
+            .starts_with("bytes */"));
+    }
+
+    #[tokio::test]
+    async fn outdated_if_range_serves_the_whole_file() {
+        let request = get("Range: bytes=0-1\r\nIf-Range: \"outdated\"\r\n").await;
+
+        let response = Response::ok_from_file("static/index.html")
+            .unwrap()
+            .ranged(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::Ok);
+        assert_eq!(response.get_header("Accept-Ranges"), Some("bytes"));
+    }
+
+    #[tokio::test]
+    async fn matching_if_range_serves_the_range() {
+        let response = Response::ok_from_file("static/index.html").unwrap();
+        let etag = response.get_header("ETag").unwrap().to_owned();
+        let request = get(&format!("Range: bytes=0-1\r\nIf-Range: {}\r\n", etag)).await;
+
+        let response = response.ranged(&request);
+
+        assert_eq!(response.status_code, HttpStatusCode::PartialContent);
     }
 }
@@ -0,0 +1,194 @@
+use std::{fs::File, io::SeekFrom};
+
+use futures_util::{stream::BoxStream, Stream, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+/// The size of the buffer used to copy files to the connection
+const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+pub enum ResponseBody {
+    /// The whole body is already in memory
+    Bytes(Vec<u8>),
+
+    /// `length` bytes of a file starting at `offset`, read from disk while writing the response
+    File {
+        file: File,
+        offset: u64,
+        length: u64,
+    },
+
+    /// Chunks produced while writing the response, the total length is not known upfront
+    Stream(BoxStream<'static, std::io::Result<Vec<u8>>>),
+
+    /// Several bodies written one after the other (ex; the parts of a multipart response)
+    Parts(Vec<ResponseBody>),
+}
+
+impl ResponseBody {
+    pub fn empty() -> Self {
+        ResponseBody::Bytes(vec![])
+    }
+
+    ///
+    /// Create a body from a whole file
+    ///
+    pub fn file(file: File) -> std::io::Result<Self> {
+        let length = file.metadata()?.len();
+
+        Ok(ResponseBody::File {
+            file,
+            offset: 0,
+            length,
+        })
+    }
+
+    pub fn stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static,
+    {
+        ResponseBody::Stream(stream.boxed())
+    }
+
+    ///
+    /// Get the body length in bytes, it's None for streams
+    ///
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            ResponseBody::Bytes(bytes) => Some(bytes.len() as u64),
+            ResponseBody::File { length, .. } => Some(*length),
+            ResponseBody::Stream(_) => None,
+            ResponseBody::Parts(parts) => parts.iter().map(|part| part.len()).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    ///
+    /// Get the body content if it's already in memory
+    ///
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ResponseBody::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Write the body after the response head
+    ///
+    /// Bodies of unknown length are written using the chunked transfer coding if `chunked` is true,
+    /// otherwise they are written as is and the end of the body is marked by closing the connection.
+    ///
+    pub(crate) async fn write_to<W: AsyncWrite + Unpin>(
+        self,
+        writer: &mut W,
+        chunked: bool,
+    ) -> std::io::Result<()> {
+        let chunked = chunked && self.len().is_none();
+
+        for part in self.flatten() {
+            part.write_part(writer, chunked).await?;
+        }
+
+        if chunked {
+            writer.write_all(b"0\r\n\r\n").await?;
+        }
+
+        writer.flush().await
+    }
+
+    /// Get the list of bodies to write, with nested `Parts` expanded
+    fn flatten(self) -> Vec<ResponseBody> {
+        match self {
+            ResponseBody::Parts(parts) => parts.into_iter().flat_map(Self::flatten).collect(),
+            body => vec![body],
+        }
+    }
+
+    ///
+    /// Write a single (not `Parts`) body, as one or more chunks if `chunked` is true
+    ///
+    async fn write_part<W: AsyncWrite + Unpin>(
+        self,
+        writer: &mut W,
+        chunked: bool,
+    ) -> std::io::Result<()> {
+        match self {
+            ResponseBody::Bytes(bytes) => write_data(writer, &bytes, chunked).await?,
+            ResponseBody::File {
+                file,
+                offset,
+                length,
+            } => {
+                let mut file = tokio::fs::File::from_std(file);
+                file.seek(SeekFrom::Start(offset)).await?;
+
+                let mut file = file.take(length);
+                let mut buffer = vec![0; FILE_BUFFER_SIZE];
+                let mut remaining = length;
+                while remaining > 0 {
+                    let read = file.read(&mut buffer).await?;
+                    if read == 0 {
+                        // the file got shorter after the Content-Length was sent
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    }
+
+                    write_data(writer, &buffer[..read], chunked).await?;
+                    remaining -= read as u64;
+                }
+            }
+            ResponseBody::Stream(mut stream) => {
+                while let Some(chunk) = stream.next().await {
+                    write_data(writer, &chunk?, chunked).await?;
+                }
+            }
+            ResponseBody::Parts(_) => unreachable!("parts are flattened before writing"),
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Write a piece of the body, framed as a chunk if `chunked` is true
+///
+async fn write_data<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    data: &[u8],
+    chunked: bool,
+) -> std::io::Result<()> {
+    if !chunked {
+        return writer.write_all(data).await;
+    }
+
+    // an empty chunk marks the end of a chunked body
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    writer
+        .write_all(format!("{:X}\r\n", data.len()).as_bytes())
+        .await?;
+    writer.write_all(data).await?;
+    writer.write_all(b"\r\n").await
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        ResponseBody::Bytes(bytes)
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(text: String) -> Self {
+        ResponseBody::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(text: &str) -> Self {
+        ResponseBody::Bytes(text.as_bytes().to_vec())
+    }
+}
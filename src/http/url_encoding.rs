@@ -0,0 +1,78 @@
+use std::borrow::Cow;
+
+use percent_encoding::percent_decode_str;
+
+use super::request_param::RequestParam;
+
+///
+/// Percent-decode a path segment, ex; `caf%C3%A9` into `café`
+///
+/// Unlike query strings `+` is not a space in a path.
+/// Invalid UTF-8 sequences are replaced with `U+FFFD`.
+///
+pub fn decode_path_segment(segment: &str) -> Cow<'_, str> {
+    percent_decode_str(segment).decode_utf8_lossy()
+}
+
+///
+/// Parse an `application/x-www-form-urlencoded` input, a query string or a form body,
+/// into its params in the order they appear
+///
+/// Keys and values are percent-decoded and `+` is a space, ex; `q=rust+server&tag=a%26b`.
+/// Repeated keys are all kept, and a param without `=` has an empty value.
+///
+pub fn parse_urlencoded(input: &[u8]) -> Vec<RequestParam> {
+    form_urlencoded::parse(input)
+        .map(|(key, value)| RequestParam {
+            key: key.into_owned(),
+            value: value.into_owned(),
+        })
+        .collect()
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn pairs(input: &str) -> Vec<(String, String)> {
+        parse_urlencoded(input.as_bytes())
+            .into_iter()
+            .map(|param| (param.key, param.value))
+            .collect()
+    }
+
+    #[test]
+    fn path_segment_is_percent_decoded() {
+        assert_eq!(decode_path_segment("caf%C3%A9"), "café");
+        assert_eq!(decode_path_segment("a%20b+c"), "a b+c");
+        assert_eq!(decode_path_segment("100%"), "100%");
+        assert_eq!(decode_path_segment("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn urlencoded_params_are_decoded() {
+        assert_eq!(
+            pairs("q=rust+server&tag=a%26b&name=caf%C3%A9"),
+            vec![
+                ("q".to_owned(), "rust server".to_owned()),
+                ("tag".to_owned(), "a&b".to_owned()),
+                ("name".to_owned(), "café".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_and_keyless_params_are_kept() {
+        assert_eq!(
+            pairs("tag=a&debug&tag=b&&empty="),
+            vec![
+                ("tag".to_owned(), "a".to_owned()),
+                ("debug".to_owned(), "".to_owned()),
+                ("tag".to_owned(), "b".to_owned()),
+                ("empty".to_owned(), "".to_owned()),
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod app;
 pub mod http;
 pub mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
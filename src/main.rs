@@ -3,6 +3,7 @@ const SERVER_ADDRESS: &str = "localhost:7070";
 use std::thread;
 use std::time::Duration;
 
+use rs_server::app::extract::Path;
 use rs_server::app::App;
 use rs_server::http::request::Request;
 use rs_server::http::response::Response;
@@ -21,10 +22,12 @@ async fn main() -> std::io::Result<()> {
                 let name: String = request.get_query_param("name").unwrap_or("Ali".to_owned());
                 Response::ok(format!("Hi {}", name).as_str())
             })
-            .get("/users/{id}", |request: Request| -> Response {
-                let id: u32 = request.get_route_param("id").unwrap_or(1);
+            .get("/users/{id}", |Path(id): Path<u32>| async move {
                 Response::ok(format!("Hi user => {}", id).as_str())
-            }),
+            })
+            .static_dir("/assets", "static/")
+            .cache_control("/assets", "public, max-age=3600")
+            .not_found_page("static/404.html"),
     )
     .listen(SERVER_ADDRESS)?
     .run()
@@ -1,36 +1,124 @@
 #![allow(dead_code)]
 
-use std::{io::Write, net::TcpListener};
+#[cfg(feature = "tls")]
+use std::path::Path;
+use std::{
+    future::Future,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(feature = "tls")]
+use tokio::net::TcpStream;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf},
+    net::TcpListener,
+    sync::Semaphore,
+    time::{timeout, Instant, Sleep},
+};
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
 
+#[cfg(feature = "tls")]
+use crate::tls::{TlsConfig, TlsHandshake};
 use crate::{
     app::App,
-    http::{request::Request, response::Response},
+    http::{
+        http_method::HttpMethod,
+        multipart::{MultipartError, MultipartLimits},
+        request::{Request, RequestParsingError},
+        response::{HttpStatusCode, Response},
+    },
 };
 
 /// The default number of worker threads the server has in its pool of threads
 const THREAD_POOL_SIZE: usize = 4;
 
+/// The default maximum request body size in bytes (10 MiB)
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// The default time a persistent connection can stay idle waiting for the next request
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default time a client can stay silent in the middle of sending a request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default number of requests served on a single persistent connection
+const MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
+/// How long to wait before accepting connections again after accepting one failed
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 pub struct Server {
     /// The address (IP:PORT) this server is bound to
     address: String,
 
-    /// the main application for request handling
-    app: App,
+    /// the main application for request handling, shared by all the connections
+    app: Arc<App>,
 
     /// if we fail to bind to the supplied address this will be None
-    listener: Option<TcpListener>,
+    listener: Option<StdTcpListener>,
 
-    /// max number of worker threads
+    /// max number of sync route handlers running at the same time on the blocking thread pool
     workers_no: usize,
+
+    /// requests with a body larger than this (in bytes) are rejected
+    max_body_size: usize,
+
+    /// how long a connection can stay idle before it is closed
+    keep_alive_timeout: Duration,
+
+    /// how long a client can stay silent once it started sending a request
+    read_timeout: Duration,
+
+    /// the connection is closed after serving this many requests
+    max_requests_per_connection: usize,
+
+    /// `multipart/form-data` bodies are limited by these instead of `max_body_size`
+    multipart_limits: MultipartLimits,
+
+    /// if set, connections are served over TLS (HTTPS)
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+/// The per-server settings every connection task needs
+#[derive(Clone)]
+struct ConnectionContext {
+    app: Arc<App>,
+
+    /// permits to run a sync handler on the blocking thread pool, one permit per worker
+    workers: Arc<Semaphore>,
+
+    max_body_size: usize,
+
+    keep_alive_timeout: Duration,
+
+    read_timeout: Duration,
+
+    max_requests_per_connection: usize,
+
+    multipart_limits: Arc<MultipartLimits>,
 }
 
 impl Server {
     pub fn new(app: App) -> Self {
         Self {
             address: String::new(),
-            app,
+            app: Arc::new(app),
             listener: None,
             workers_no: THREAD_POOL_SIZE,
+            max_body_size: MAX_BODY_SIZE,
+            keep_alive_timeout: KEEP_ALIVE_TIMEOUT,
+            read_timeout: READ_TIMEOUT,
+            max_requests_per_connection: MAX_REQUESTS_PER_CONNECTION,
+            multipart_limits: MultipartLimits::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 
@@ -41,78 +129,1134 @@ impl Server {
     pub fn listen(mut self, address: &str) -> std::io::Result<Self> {
         self.address = address.to_owned();
 
-        let bind_result = TcpListener::bind(address);
-        if let Ok(listener) = bind_result {
-            self.listener = Some(listener);
-        } else {
-            return Err(bind_result.unwrap_err());
-        }
+        // the socket is bound right away so binding errors are reported here,
+        // it is handed over to tokio once the server starts running
+        let listener = StdTcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        self.listener = Some(listener);
+
+        Ok(self)
+    }
+
+    ///
+    /// Start listening on the provided address and serve HTTPS,
+    /// with the PEM certificate chain and private key as the default certificate
+    ///
+    /// The certificate files are checked for changes every 10 seconds (see `set_tls_reload_interval`)
+    /// and reloaded, so a renewed certificate is used by the new connections without a restart.
+    ///
+    /// It return std::io::Error if the certificate can not be loaded
+    /// or if it can not listen on the provided address.
+    ///
+    #[cfg(feature = "tls")]
+    pub fn listen_tls<P: AsRef<Path>>(
+        mut self,
+        address: &str,
+        cert_path: P,
+        key_path: P,
+    ) -> std::io::Result<Self> {
+        self.tls = Some(TlsConfig::new(cert_path.as_ref(), key_path.as_ref())?);
+        self.listen(address)
+    }
 
+    ///
+    /// Use another certificate for the clients asking for `server_name` (SNI),
+    /// the clients asking for other names get the default certificate
+    ///
+    /// `server_name` can be a wildcard matching a single label, ex; `*.example.com`.
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen_tls`
+    ///
+    #[cfg(feature = "tls")]
+    pub fn add_tls_certificate<P: AsRef<Path>>(
+        mut self,
+        server_name: &str,
+        cert_path: P,
+        key_path: P,
+    ) -> std::io::Result<Self> {
+        self.tls_config("add_tls_certificate").add_certificate(
+            server_name,
+            cert_path.as_ref(),
+            key_path.as_ref(),
+        )?;
         Ok(self)
     }
 
+    ///
+    /// Set the protocols offered with ALPN in order of preference, ex; `&["http/1.1"]`
+    ///
+    /// The server only speaks HTTP/1.1, other protocols have to be handled by the `on_tls_handshake` hook.
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen_tls`
+    ///
+    #[cfg(feature = "tls")]
+    pub fn set_alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        self.tls_config("set_alpn_protocols")
+            .set_alpn_protocols(protocols);
+        self
+    }
+
+    ///
+    /// Call `hook` after every TLS handshake with the negotiated server name and ALPN protocol,
+    /// the connection is closed if it returns false
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen_tls`
+    ///
+    #[cfg(feature = "tls")]
+    pub fn on_tls_handshake<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(&TlsHandshake) -> bool + Send + Sync + 'static,
+    {
+        self.tls_config("on_tls_handshake")
+            .set_handshake_hook(Arc::new(hook));
+        self
+    }
+
+    ///
+    /// Set how often the certificate files are checked for changes
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen_tls` or if `reload_interval` is zero
+    ///
+    #[cfg(feature = "tls")]
+    pub fn set_tls_reload_interval(mut self, reload_interval: Duration) -> Self {
+        self.tls_config("set_tls_reload_interval")
+            .set_reload_interval(reload_interval);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls_config(&mut self, method: &str) -> &mut TlsConfig {
+        self.tls
+            .as_mut()
+            .unwrap_or_else(|| panic!("`listen_tls` must be called before `{}`", method))
+    }
+
+    ///
+    /// Set the max number of sync route handlers that can run at the same time
+    ///
+    /// Sync handlers are blocking code, so they are executed on tokio's blocking thread pool
+    /// and this limits how many of its threads the server uses. Async handlers are not limited.
+    ///
+    /// # Panic
+    /// this method will panic if `workers_no` is zero
+    ///
     pub fn set_workers_no(mut self, workers_no: usize) -> Self {
+        assert!(workers_no > 0, "the server needs at least one worker");
+
         self.workers_no = workers_no;
         self
     }
 
+    pub fn set_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    ///
+    /// Set how long a connection can stay idle waiting for the next request before it is closed
+    ///
+    pub fn set_keep_alive_timeout(mut self, keep_alive_timeout: Duration) -> Self {
+        self.keep_alive_timeout = keep_alive_timeout;
+        self
+    }
+
+    ///
+    /// Set how long a client can stay silent in the middle of sending a request,
+    /// ex; between two parts of a slow upload
+    ///
+    /// It's not a limit on the time to send the whole request, a slow client
+    /// is served as long as it keeps sending data.
+    ///
+    pub fn set_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    ///
+    /// Set the max number of requests served on a single connection,
+    /// the response to the last one is sent with `Connection: close`
+    ///
+    /// # Panic
+    /// this method will panic if `max_requests` is zero
+    ///
+    pub fn set_max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        assert!(
+            max_requests > 0,
+            "a connection has to serve at least one request"
+        );
+
+        self.max_requests_per_connection = max_requests;
+        self
+    }
+
+    ///
+    /// Set the limits of `multipart/form-data` request bodies
+    ///
+    /// Multipart bodies are parsed while they are read and their large parts are spooled
+    /// to temporary files, so they are limited by `limits` instead of the max body size.
+    ///
+    pub fn set_multipart_limits(mut self, limits: MultipartLimits) -> Self {
+        self.multipart_limits = limits;
+        self
+    }
+
+    ///
+    /// Get the address the server is listening on,
+    /// this is useful when listening on port 0 to let the OS pick a free port
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen`
+    ///
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener
+            .as_ref()
+            .expect("`listen` must be called before `local_addr`")
+            .local_addr()
+    }
+
     ///
     /// runs the app and start accepting connections
-    ///  
+    ///
+    /// Every connection is served by its own task, so a slow client or a slow handler
+    /// does not stop the server from accepting other connections.
+    ///
+    /// # Panic
+    /// this method will panic if called before `listen`
+    ///
     pub async fn run(&mut self) -> std::io::Result<()> {
+        let listener = TcpListener::from_std(
+            self.listener
+                .take()
+                .expect("`listen` must be called before `run`"),
+        )?;
+
         println!("Server is listening on {}", self.address);
 
-        for stream in self.listener.as_mut().unwrap().incoming() {
-            println!("Connection estaplished");
+        let context = ConnectionContext {
+            app: Arc::clone(&self.app),
+            workers: Arc::new(Semaphore::new(self.workers_no)),
+            max_body_size: self.max_body_size,
+            keep_alive_timeout: self.keep_alive_timeout,
+            read_timeout: self.read_timeout,
+            max_requests_per_connection: self.max_requests_per_connection,
+            multipart_limits: Arc::new(self.multipart_limits.clone()),
+        };
 
-            if let Err(e) = stream {
-                eprintln!("{}", e);
-                return Err(e);
+        #[cfg(feature = "tls")]
+        let tls = match self.tls.take() {
+            Some(tls) => {
+                tls.watch();
+                Some((tls.acceptor()?, Arc::new(tls)))
             }
+            None => None,
+        };
 
-            let mut stream = stream.unwrap();
-
-            // try to read request data from the TcpStream and construct a basic HTTP Request object from it
-            // this will fail if the request was not an HTTP Request
-            match Request::initial_parse(&stream) {
-                Ok(mut request) => {
-                    // if we got an HTTP Request,
-                    //
-                    // 1. we try to find any registered handler that matches the request method and path
-                    match self.app.get_route(request.method, &request.base_path) {
-                        // 2. if we found one we dispatch a job useing the thread pool to execute the handler
-                        Some(route) => {
-                            // after we get a matching route object we can now continue parsing the whole request object
-                            match request.complete_parsing(&stream, &route) {
-                                Ok(_) => {}
-                                Err(_) => {
-                                    eprintln!("Faild to complete parsing request {:?}", request);
-                                    continue;
-                                }
-                            }
-
-                            tokio::spawn(async move {
-                                let response = (route.handler)(request);
-                                stream.write_all(response.as_string().as_bytes()).unwrap();
-                            });
-                        }
-
-                        // 3. if we did not found any handler we return NOT FOUND error
-                        None => {
-                            tokio::spawn(async move {
-                                let response = Response::not_found();
-                                stream.write_all(response.as_string().as_bytes()).unwrap();
-                            });
-                        }
-                    };
-                }
-                Err(_) => {
-                    eprintln!("Got Non-Http request");
+        loop {
+            // failing to accept one connection (ex; too many open files) should not stop the server
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+
+                    // the error usually lasts a while (ex; no file descriptors left),
+                    // retrying right away would only spin
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
                     continue;
                 }
             };
+
+            println!("Connection estaplished");
+
+            let context = context.clone();
+
+            #[cfg(feature = "tls")]
+            if let Some((acceptor, tls)) = &tls {
+                let (acceptor, tls) = (acceptor.clone(), Arc::clone(tls));
+                tokio::spawn(async move {
+                    report(handle_tls_connection(stream, acceptor, &tls, context).await)
+                });
+                continue;
+            }
+
+            tokio::spawn(async move { report(handle_connection(stream, context).await) });
         }
+    }
+}
+
+/// Log the error a connection ended with
+fn report(result: std::io::Result<()>) {
+    match result {
+        Ok(()) => {}
+
+        // the client went away before getting the whole response
+        Err(e) if is_disconnect(&e) => {}
 
-        Ok(())
+        Err(e) => eprintln!("Failed to serve connection: {}", e),
+    }
+}
+
+/// Check if an IO error means the client closed the connection
+fn is_disconnect(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+///
+/// Serve requests from the connection until the client closes it, asks for it to be closed,
+/// or it stays idle for too long
+///
+/// Pipelined requests are read and answered one after the other, so the responses
+/// are always sent in the same order the requests were received.
+///
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    context: ConnectionContext,
+) -> std::io::Result<()> {
+    let (read_half, mut write_half) = tokio::io::split(stream);
+    let mut reader = BufReader::new(read_half);
+
+    for served in 1.. {
+        let Some((mut response, info)) = serve_request(&mut reader, &context).await else {
+            break;
+        };
+
+        // the handler can also ask for the connection to be closed,
+        // and a body of unknown length without chunked encoding is ended by closing the connection
+        let keep_alive = info.keep_alive
+            && served < context.max_requests_per_connection
+            && (info.chunked || response.body.len().is_some())
+            && !response
+                .get_header("Connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+
+        response.set_header(
+            "Connection",
+            if keep_alive { "keep-alive" } else { "close" },
+        );
+        if info.head {
+            response
+                .write_head_to(&mut write_half, info.chunked)
+                .await?;
+        } else {
+            response.write_to(&mut write_half, info.chunked).await?;
+        }
+
+        if !keep_alive {
+            break;
+        }
+    }
+
+    // over TLS this sends the close_notify alert, the client may already be gone
+    let _ = write_half.shutdown().await;
+
+    Ok(())
+}
+
+///
+/// Do the TLS handshake then serve the connection like a plain one,
+/// unless the handshake hook refuses it
+///
+#[cfg(feature = "tls")]
+async fn handle_tls_connection(
+    stream: TcpStream,
+    acceptor: TlsAcceptor,
+    tls: &TlsConfig,
+    context: ConnectionContext,
+) -> std::io::Result<()> {
+    // a client that does not finish the handshake is closed like an idle one
+    let stream = match timeout(context.keep_alive_timeout, acceptor.accept(stream)).await {
+        Ok(stream) => stream?,
+        Err(_) => return Ok(()),
+    };
+
+    let (_, connection) = stream.get_ref();
+    let handshake = TlsHandshake {
+        server_name: connection.server_name().map(str::to_owned),
+        alpn_protocol: connection.alpn_protocol().map(<[u8]>::to_vec),
+    };
+    if !tls.accepts(&handshake) {
+        return Ok(());
+    }
+
+    handle_connection(stream, context).await
+}
+
+/// What the connection needs to know about a request after its handler took ownership of it
+struct RequestInfo {
+    /// the client wants to keep the connection open
+    keep_alive: bool,
+
+    /// the client understands the chunked transfer coding (HTTP/1.1 and later)
+    chunked: bool,
+
+    /// the client only wants the response head (HEAD request)
+    head: bool,
+}
+
+///
+/// Read a single request from the connection and dispatch it to the matching route handler
+///
+/// It returns the response with what we need to know about the request to send it,
+/// or None if the connection should be closed without a response (the client closed it or went idle).
+///
+async fn serve_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    context: &ConnectionContext,
+) -> Option<(Response, RequestInfo)> {
+    // the keep-alive timeout only applies while waiting for the next request,
+    // once it started the client has `read_timeout` between two reads
+    match timeout(context.keep_alive_timeout, reader.fill_buf()).await {
+        Ok(Ok(buffer)) if !buffer.is_empty() => {}
+
+        // the client closed the connection, or kept it idle for too long
+        _ => return None,
+    }
+    let mut reader = ReadTimeout::new(reader, context.read_timeout);
+
+    // try to read request data from the TcpStream and construct a basic HTTP Request object from it
+    // this will fail if the request was not an HTTP Request
+    let mut request = match Request::initial_parse(&mut reader).await {
+        Ok(request) => request,
+
+        // a client still connected gets an answer, even if it stopped sending the request
+        Err(RequestParsingError::Io(ErrorKind::TimedOut)) => {
+            return Some(error_response(context, HttpStatusCode::RequestTimeout))
+        }
+
+        // the client closed the connection
+        Err(RequestParsingError::UnexpectedEof) | Err(RequestParsingError::Io(_)) => return None,
+
+        // the request line is the only line that can be too large here
+        Err(RequestParsingError::HeadersTooLarge) => {
+            return Some(error_response(context, HttpStatusCode::UriTooLong))
+        }
+
+        Err(e) => {
+            eprintln!("Got Non-Http request: {:?}", e);
+            return Some(error_response(context, HttpStatusCode::BadRequest));
+        }
+    };
+
+    // if we got an HTTP Request,
+    //
+    // 1. we try to find any registered handler that matches the request method and path
+    let route = context.app.get_route(&request.method, &request.base_path);
+
+    // the whole request is read even if there is no matching route,
+    // so the next request on this connection starts at the right place
+    let parsing = request.complete_parsing_with_limits(
+        &mut reader,
+        route.as_ref(),
+        context.max_body_size,
+        &context.multipart_limits,
+    );
+    match parsing.await {
+        Ok(_) => {}
+
+        Err(RequestParsingError::Io(ErrorKind::TimedOut)) => {
+            return Some(error_response(context, HttpStatusCode::RequestTimeout))
+        }
+
+        // the client closed the connection, ex; in the middle of a multipart upload
+        Err(
+            RequestParsingError::UnexpectedEof
+            | RequestParsingError::IncompleteBody
+            | RequestParsingError::Io(_)
+            | RequestParsingError::Multipart(MultipartError::Io(_)),
+        ) => return None,
+
+        Err(e) => {
+            // only the request line, the headers can have credentials and session IDs
+            eprintln!(
+                "Faild to complete parsing request {:?}: {:?}",
+                request.line, e
+            );
+
+            let status_code = match e {
+                RequestParsingError::BodyTooLarge => HttpStatusCode::ContentTooLarge,
+                RequestParsingError::HeadersTooLarge => HttpStatusCode::RequestHeaderFieldsTooLarge,
+                RequestParsingError::UnsupportedTransferEncoding => HttpStatusCode::NotImplemented,
+                RequestParsingError::Multipart(e) => e.status_code(),
+                _ => HttpStatusCode::BadRequest,
+            };
+            return Some(error_response(context, status_code));
+        }
+    }
+
+    let path = request.base_path.clone();
+    let info = RequestInfo {
+        keep_alive: request.keep_alive(),
+        chunked: request.http_version != "HTTP/1.0",
+        head: request.method == HttpMethod::Head,
+    };
+
+    // 2. we run the request through the app middlewares and the route handler,
+    //    if we did not found any handler the response is NOT FOUND error
+    let response = context
+        .app
+        .dispatch(request, route, Arc::clone(&context.workers))
+        .await;
+
+    Some((apply_cache_policy(response, &path, &context.app), info))
+}
+
+///
+/// A reader that fails with `TimedOut` if reading waits for more than `timeout`,
+/// the time starts again after every successful read
+///
+struct ReadTimeout<R> {
+    reader: R,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl<R> ReadTimeout<R> {
+    fn new(reader: R, timeout: Duration) -> Self {
+        Self {
+            reader,
+            timeout,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+}
+
+/// Wait for the read deadline while the reader is not ready
+fn poll_deadline(deadline: &mut Pin<Box<Sleep>>, cx: &mut Context<'_>) -> Poll<std::io::Error> {
+    deadline.as_mut().poll(cx).map(|_| {
+        std::io::Error::new(
+            ErrorKind::TimedOut,
+            "the client stopped sending the request",
+        )
+    })
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ReadTimeout<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let Self {
+            reader,
+            timeout,
+            deadline,
+        } = self.get_mut();
+
+        match Pin::new(reader).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                deadline.as_mut().reset(Instant::now() + *timeout);
+                Poll::Ready(result)
+            }
+            Poll::Pending => poll_deadline(deadline, cx).map(Err),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for ReadTimeout<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        let Self {
+            reader,
+            timeout,
+            deadline,
+        } = self.get_mut();
+
+        match Pin::new(reader).poll_fill_buf(cx) {
+            Poll::Ready(result) => {
+                deadline.as_mut().reset(Instant::now() + *timeout);
+                Poll::Ready(result)
+            }
+            Poll::Pending => poll_deadline(deadline, cx).map(Err),
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.get_mut().reader).consume(amt);
+    }
+}
+
+///
+/// Build the response to a request that could not be parsed,
+/// the connection is closed after it as the next request can not be found
+///
+fn error_response(
+    context: &ConnectionContext,
+    status_code: HttpStatusCode,
+) -> (Response, RequestInfo) {
+    // the client HTTP version is unknown, a body of unknown length is ended by closing the connection
+    let info = RequestInfo {
+        keep_alive: false,
+        chunked: false,
+        head: false,
+    };
+
+    (context.app.error_response(status_code), info)
+}
+
+///
+/// Set the Cache-Control policy configured on the app for the request path,
+/// only successful responses without their own Cache-Control are changed
+///
+fn apply_cache_policy(mut response: Response, path: &str, app: &App) -> Response {
+    let code = response.status_code.get_code();
+    let cacheable = (200..300).contains(&code) || code == 304;
+
+    if cacheable && response.get_header("Cache-Control").is_none() {
+        if let Some(policy) = app.get_cache_control(path) {
+            response.set_header("Cache-Control", policy);
+        }
+    }
+
+    response
+}
+
+/// Unit Tests
+#[cfg(test)]
+mod tests {
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::http::{request::Request, response_body::ResponseBody};
+
+    async fn start(server: Server) -> SocketAddr {
+        let mut server = server.listen("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+
+        tokio::spawn(async move { server.run().await });
+
+        address
+    }
+
+    fn app() -> App {
+        App::default()
+            .get("/one", |_r: Request| Response::ok("one"))
+            .get("/two", |_r: Request| async { Response::ok("two") })
+            .get("/api/no-store", |_r: Request| {
+                let mut response = Response::ok("fresh");
+                response.set_header("Cache-Control", "no-store");
+                response
+            })
+            .cache_control("/one", "max-age=60")
+            .cache_control("/", "no-cache")
+    }
+
+    /// Send the raw requests and read everything until the server closes the connection
+    async fn send(address: SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .expect("the server did not close the connection")
+            .unwrap();
+
+        response
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests_are_answered_in_order() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(
+            address,
+            "GET /two HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\nGET /one HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let two = response.find("two").unwrap();
+        let missing = response.find("404").unwrap();
+        let one = response.find("one").unwrap();
+        assert!(two < missing && missing < one);
+
+        assert_eq!(response.matches("Connection: keep-alive").count(), 2);
+        assert!(response[missing..one].contains("Connection: close"));
+    }
+
+    #[tokio::test]
+    async fn http_1_0_connections_are_closed() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(
+            address,
+            "GET /one HTTP/1.0\r\n\r\nGET /two HTTP/1.0\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.contains("Connection: close"));
+        assert!(!response.contains("two"));
+    }
+
+    #[tokio::test]
+    async fn connection_is_closed_after_max_requests() {
+        let address = start(Server::new(app()).set_max_requests_per_connection(2)).await;
+
+        let response = send(address, "GET /one HTTP/1.1\r\n\r\n".repeat(3).as_str()).await;
+
+        assert_eq!(response.matches("one").count(), 2);
+        assert_eq!(response.matches("Connection: keep-alive").count(), 1);
+        assert_eq!(response.matches("Connection: close").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn idle_connection_is_closed() {
+        let address =
+            start(Server::new(app()).set_keep_alive_timeout(Duration::from_millis(100))).await;
+
+        let response = send(address, "GET /one HTTP/1.1\r\n\r\n").await;
+
+        assert!(response.contains("Connection: keep-alive"));
+        assert!(response.ends_with("one"));
+    }
+
+    #[tokio::test]
+    async fn slow_but_active_client_is_served() {
+        let app = app().post("/echo", |r: Request| {
+            Response::ok(r.text().unwrap_or_default())
+        });
+        let server = Server::new(app)
+            .set_keep_alive_timeout(Duration::from_millis(300))
+            .set_read_timeout(Duration::from_millis(300));
+        let address = start(server).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 10\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        // the whole body takes longer than both timeouts, but the client is never silent for long
+        for byte in b"0123456789" {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            stream.write_all(&[*byte]).await.unwrap();
+        }
+
+        let mut response = String::new();
+        timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .expect("the server did not close the connection")
+            .unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("0123456789"));
+    }
+
+    #[tokio::test]
+    async fn stalled_request_gets_408() {
+        let server = Server::new(app()).set_read_timeout(Duration::from_millis(100));
+        let address = start(server).await;
+
+        for stalled in [
+            "GET /one HTTP/1.1\r\nHost: local",
+            "POST /one HTTP/1.1\r\nContent-Length: 10\r\n\r\n01234",
+        ] {
+            let response = send(address, stalled).await;
+
+            assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+            assert!(response.contains("Connection: close\r\n"));
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_policy_of_the_most_specific_prefix_is_applied() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(
+            address,
+            "GET /one HTTP/1.1\r\n\r\nGET /two HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\nGET /api/no-store HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let responses: Vec<_> = response.split("HTTP/1.1 ").skip(1).collect();
+        assert!(responses[0].contains("Cache-Control: max-age=60\r\n"));
+        assert!(responses[1].contains("Cache-Control: no-cache\r\n"));
+        assert!(!responses[2].contains("Cache-Control"));
+        assert!(responses[3].contains("Cache-Control: no-store\r\n"));
+        assert!(!responses[3].contains("no-cache"));
+    }
+
+    #[tokio::test]
+    async fn head_request_gets_the_get_response_head() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(
+            address,
+            "HEAD /one HTTP/1.1\r\n\r\nGET /two HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let responses: Vec<_> = response.split("HTTP/1.1 ").skip(1).collect();
+        assert!(responses[0].starts_with("200 OK\r\n"));
+        assert!(responses[0].contains("Content-Length: 3\r\n"));
+        assert!(responses[0].ends_with("\r\n\r\n"));
+        assert!(responses[1].ends_with("two"));
+    }
+
+    #[tokio::test]
+    async fn malformed_request_gets_400_and_the_connection_is_closed() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(address, "GET / HTTP/1.1\r\nHost : localhost\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[tokio::test]
+    async fn non_http_request_gets_400() {
+        let address = start(Server::new(app())).await;
+
+        let response = send(address, "HELLO\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[tokio::test]
+    async fn too_large_body_gets_413() {
+        let address = start(Server::new(app()).set_max_body_size(4)).await;
+
+        let response = send(
+            address,
+            "POST /one HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+    }
+
+    #[tokio::test]
+    async fn multipart_uploads_are_limited_by_the_multipart_limits() {
+        let app = app().post("/upload", |mut r: Request| {
+            let parts = r.multipart().unwrap();
+            Response::ok(&format!("{} {}", parts[0].len(), parts[0].path().is_some()))
+        });
+        let limits = MultipartLimits::default()
+            .set_max_total_size(1024)
+            .set_max_memory_size(16);
+        let address = start(
+            Server::new(app)
+                .set_max_body_size(16)
+                .set_multipart_limits(limits),
+        )
+        .await;
+        let upload = |size: usize| {
+            let body = format!(
+                "--b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"f\"\r\n\r\n{}\r\n--b--\r\n",
+                "x".repeat(size)
+            );
+            format!(
+                "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        };
+
+        let response = send(address, &upload(512)).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("512 true"));
+
+        let response = send(address, &upload(2048)).await;
+        assert!(response.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        // the client goes away in the middle of the upload
+        let upload = upload(512);
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(&upload.as_bytes()[..upload.len() - 100])
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut response = String::new();
+        timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .expect("the server did not close the connection")
+            .unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn error_handlers_build_the_error_responses() {
+        let app = app().on_error(HttpStatusCode::BadRequest, |response: Response| {
+            response.with_body("bad request")
+        });
+        let address = start(Server::new(app)).await;
+
+        let response = send(address, "GET / HTTP/1.1\r\nHost : localhost\r\n\r\n").await;
+
+        assert!(response.ends_with("\r\n\r\nbad request"));
+    }
+
+    #[tokio::test]
+    async fn error_response_of_unknown_length_is_not_chunked() {
+        let app = app().on_error(HttpStatusCode::BadRequest, |response: Response| {
+            let chunks = vec![Ok(b"bad ".to_vec()), Ok(b"request".to_vec())];
+            response.with_body(ResponseBody::stream(futures_util::stream::iter(chunks)))
+        });
+        let address = start(Server::new(app)).await;
+
+        let response = send(address, "GET / HTTP/1.0\r\nHost : localhost\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(!response.contains("Transfer-Encoding"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("\r\n\r\nbad request"));
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_gets_500_and_the_connection_is_kept() {
+        let app = app().get("/panic", |r: Request| async move {
+            Response::ok(r.header("X-Missing").unwrap())
+        });
+        let address = start(Server::new(app)).await;
+
+        let response = send(
+            address,
+            "GET /panic HTTP/1.1\r\n\r\nGET /two HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let responses: Vec<_> = response.split("HTTP/1.1 ").skip(1).collect();
+        assert!(responses[0].starts_with("500 Internal Server Error\r\n"));
+        assert!(responses[1].ends_with("two"));
+    }
+
+    #[cfg(feature = "tls")]
+    mod tls {
+
+        use std::path::PathBuf;
+
+        use rcgen::{generate_simple_self_signed, CertifiedKey, KeyPair};
+        use rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore};
+        use tempfile::TempDir;
+        use tokio_rustls::{client::TlsStream, TlsConnector};
+
+        use super::*;
+
+        fn certificate(name: &str) -> CertifiedKey<KeyPair> {
+            generate_simple_self_signed(vec![name.to_owned()]).unwrap()
+        }
+
+        /// Write the PEM files of a certificate, it returns the certificate and key paths
+        fn write(dir: &TempDir, name: &str, cert: &CertifiedKey<KeyPair>) -> (PathBuf, PathBuf) {
+            let cert_path = dir.path().join(format!("{}.crt", name));
+            let key_path = dir.path().join(format!("{}.key", name));
+            std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+            std::fs::write(&key_path, cert.signing_key.serialize_pem()).unwrap();
+
+            (cert_path, key_path)
+        }
+
+        async fn start_tls(mut server: Server) -> SocketAddr {
+            let address = server.local_addr().unwrap();
+
+            tokio::spawn(async move { server.run().await });
+
+            address
+        }
+
+        async fn connect(
+            address: SocketAddr,
+            server_name: &str,
+            trusted: &[&CertifiedKey<KeyPair>],
+            alpn: &[&str],
+        ) -> std::io::Result<TlsStream<TcpStream>> {
+            let mut roots = RootCertStore::empty();
+            for cert in trusted {
+                roots.add(cert.cert.der().clone()).unwrap();
+            }
+
+            let mut config =
+                ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                    .with_safe_default_protocol_versions()
+                    .unwrap()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+            config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+
+            let stream = TcpStream::connect(address).await?;
+            TlsConnector::from(Arc::new(config))
+                .connect(
+                    ServerName::try_from(server_name.to_owned()).unwrap(),
+                    stream,
+                )
+                .await
+        }
+
+        /// The certificate the server sent on a connection
+        fn peer_certificate(stream: &TlsStream<TcpStream>) -> Vec<u8> {
+            stream.get_ref().1.peer_certificates().unwrap()[0].to_vec()
+        }
+
+        async fn get(stream: &mut TlsStream<TcpStream>, path: &str) -> std::io::Result<String> {
+            let request = format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path);
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut response = String::new();
+            timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+                .await
+                .expect("the server did not close the connection")?;
+
+            Ok(response)
+        }
+
+        #[tokio::test]
+        async fn https_requests_are_served() {
+            let dir = tempfile::tempdir().unwrap();
+            let cert = certificate("localhost");
+            let (cert_path, key_path) = write(&dir, "localhost", &cert);
+            let server = Server::new(app())
+                .listen_tls("127.0.0.1:0", cert_path, key_path)
+                .unwrap();
+            let address = start_tls(server).await;
+
+            let mut stream = connect(address, "localhost", &[&cert], &["http/1.1"])
+                .await
+                .unwrap();
+            assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+
+            let response = get(&mut stream, "/one").await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.ends_with("one"));
+        }
+
+        #[tokio::test]
+        async fn certificate_is_selected_by_server_name() {
+            let dir = tempfile::tempdir().unwrap();
+            let default = certificate("localhost");
+            let exact = certificate("api.example.test");
+            let wildcard = certificate("*.example.test");
+            let (cert_path, key_path) = write(&dir, "default", &default);
+            let (exact_cert, exact_key) = write(&dir, "exact", &exact);
+            let (wildcard_cert, wildcard_key) = write(&dir, "wildcard", &wildcard);
+            let server = Server::new(app())
+                .listen_tls("127.0.0.1:0", cert_path, key_path)
+                .unwrap()
+                .add_tls_certificate("API.example.test", exact_cert, exact_key)
+                .unwrap()
+                .add_tls_certificate("*.example.test", wildcard_cert, wildcard_key)
+                .unwrap();
+            let address = start_tls(server).await;
+            let trusted = [&default, &exact, &wildcard];
+
+            for (server_name, expected) in [
+                ("localhost", &default),
+                ("api.example.test", &exact),
+                ("www.example.test", &wildcard),
+            ] {
+                let stream = connect(address, server_name, &trusted, &[]).await.unwrap();
+                assert_eq!(peer_certificate(&stream), expected.cert.der().to_vec());
+            }
+        }
+
+        #[tokio::test]
+        async fn handshake_hook_can_refuse_connections() {
+            let dir = tempfile::tempdir().unwrap();
+            let cert = certificate("localhost");
+            let (cert_path, key_path) = write(&dir, "localhost", &cert);
+            let server = Server::new(app())
+                .listen_tls("127.0.0.1:0", cert_path, key_path)
+                .unwrap()
+                .set_alpn_protocols(&["h2", "http/1.1"])
+                .on_tls_handshake(|handshake| {
+                    handshake.server_name.as_deref() == Some("localhost")
+                        && handshake.alpn_protocol.as_deref() == Some(b"http/1.1")
+                });
+            let address = start_tls(server).await;
+
+            let mut accepted = connect(address, "localhost", &[&cert], &["http/1.1"])
+                .await
+                .unwrap();
+            assert!(get(&mut accepted, "/two").await.unwrap().ends_with("two"));
+
+            // the server prefers h2, which it does not speak
+            let mut refused = connect(address, "localhost", &[&cert], &["h2", "http/1.1"])
+                .await
+                .unwrap();
+            assert_eq!(refused.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+            assert!(get(&mut refused, "/two")
+                .await
+                .map_or(true, |response| response.is_empty()));
+        }
+
+        #[tokio::test]
+        async fn changed_certificate_files_are_reloaded() {
+            let dir = tempfile::tempdir().unwrap();
+            let old = certificate("localhost");
+            let new = certificate("localhost");
+            let (cert_path, key_path) = write(&dir, "localhost", &old);
+            let server = Server::new(app())
+                .listen_tls("127.0.0.1:0", &cert_path, &key_path)
+                .unwrap()
+                .set_tls_reload_interval(Duration::from_millis(20));
+            let address = start_tls(server).await;
+
+            let stream = connect(address, "localhost", &[&old, &new], &[])
+                .await
+                .unwrap();
+            assert_eq!(peer_certificate(&stream), old.cert.der().to_vec());
+
+            // a half written certificate keeps the old one
+            std::fs::write(&cert_path, "garbage").unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let stream = connect(address, "localhost", &[&old, &new], &[])
+                .await
+                .unwrap();
+            assert_eq!(peer_certificate(&stream), old.cert.der().to_vec());
+
+            write(&dir, "localhost", &new);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let stream = connect(address, "localhost", &[&old, &new], &[])
+                .await
+                .unwrap();
+            assert_eq!(peer_certificate(&stream), new.cert.der().to_vec());
+        }
+
+        #[test]
+        fn listen_tls_fails_without_a_valid_certificate() {
+            let dir = tempfile::tempdir().unwrap();
+            let (cert_path, key_path) = write(&dir, "localhost", &certificate("localhost"));
+            let other = certificate("localhost");
+            std::fs::write(&key_path, other.signing_key.serialize_pem()).unwrap();
+
+            let missing = Server::new(app()).listen_tls(
+                "127.0.0.1:0",
+                dir.path().join("missing.crt"),
+                key_path.clone(),
+            );
+            assert_eq!(missing.err().unwrap().kind(), ErrorKind::NotFound);
+
+            let mismatched = Server::new(app()).listen_tls("127.0.0.1:0", cert_path, key_path);
+            assert_eq!(mismatched.err().unwrap().kind(), ErrorKind::InvalidData);
+        }
+
+        #[test]
+        #[should_panic(expected = "the TLS certificates reload interval must not be zero")]
+        fn zero_reload_interval_is_rejected() {
+            let dir = tempfile::tempdir().unwrap();
+            let (cert_path, key_path) = write(&dir, "localhost", &certificate("localhost"));
+
+            let _ = Server::new(app())
+                .listen_tls("127.0.0.1:0", cert_path, key_path)
+                .unwrap()
+                .set_tls_reload_interval(Duration::ZERO);
+        }
+
+        #[test]
+        #[should_panic(expected = "`listen_tls` must be called before `set_alpn_protocols`")]
+        fn tls_settings_need_listen_tls() {
+            let _ = Server::new(app()).set_alpn_protocols(&["http/1.1"]);
+        }
     }
 }
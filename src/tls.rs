@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::Duration,
+};
+
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+
+/// The default time between two checks of the certificate files for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A hook that gets every TLS handshake and tells if the connection is accepted
+pub type HandshakeHook = Arc<dyn Fn(&TlsHandshake) -> bool + Send + Sync + 'static>;
+
+///
+/// What the client and the server agreed on during a TLS handshake
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsHandshake {
+    /// the host name the client asked for (SNI), None if the client did not send one
+    pub server_name: Option<String>,
+
+    /// the application protocol chosen with ALPN, ex; `http/1.1`, None if the client did not use ALPN
+    pub alpn_protocol: Option<Vec<u8>>,
+}
+
+///
+/// The TLS settings of a server, see `Server::listen_tls`
+///
+pub struct TlsConfig {
+    resolver: Arc<CertResolver>,
+
+    /// the protocols offered with ALPN in order of preference
+    alpn_protocols: Vec<Vec<u8>>,
+
+    /// the hook called after every handshake, if any
+    handshake_hook: Option<HandshakeHook>,
+
+    /// how often the certificate files are checked for changes
+    reload_interval: Duration,
+}
+
+impl TlsConfig {
+    ///
+    /// Use the certificate chain and the private key of PEM files as the default certificate
+    ///
+    /// It fails if the files can not be read, or do not hold a certificate and its private key.
+    ///
+    pub fn new(cert_path: &Path, key_path: &Path) -> std::io::Result<Self> {
+        let default = CertFiles::load(cert_path, key_path)?;
+
+        Ok(Self {
+            resolver: Arc::new(CertResolver {
+                default: RwLock::new(Arc::clone(&default.key)),
+                by_name: RwLock::new(HashMap::new()),
+                files: Mutex::new(vec![(None, default)]),
+            }),
+            alpn_protocols: vec![b"http/1.1".to_vec()],
+            handshake_hook: None,
+            reload_interval: RELOAD_INTERVAL,
+        })
+    }
+
+    ///
+    /// Use another certificate for the clients asking for `server_name` with SNI
+    ///
+    /// `server_name` can be a wildcard matching a single label, ex; `*.example.com`.
+    ///
+    pub fn add_certificate(
+        &mut self,
+        server_name: &str,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> std::io::Result<()> {
+        let files = CertFiles::load(cert_path, key_path)?;
+        let server_name = server_name.to_ascii_lowercase();
+
+        self.resolver
+            .by_name
+            .write()
+            .unwrap()
+            .insert(server_name.clone(), Arc::clone(&files.key));
+        self.resolver
+            .files
+            .lock()
+            .unwrap()
+            .push((Some(server_name), files));
+
+        Ok(())
+    }
+
+    pub fn set_alpn_protocols(&mut self, protocols: &[&str]) {
+        self.alpn_protocols = protocols
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+    }
+
+    pub fn set_handshake_hook(&mut self, hook: HandshakeHook) {
+        self.handshake_hook = Some(hook);
+    }
+
+    ///
+    /// Set how often the certificate files are checked for changes
+    ///
+    /// # Panic
+    /// this method will panic if `reload_interval` is zero
+    ///
+    pub fn set_reload_interval(&mut self, reload_interval: Duration) {
+        assert!(
+            !reload_interval.is_zero(),
+            "the TLS certificates reload interval must not be zero"
+        );
+
+        self.reload_interval = reload_interval;
+    }
+
+    pub(crate) fn acceptor(&self) -> std::io::Result<TlsAcceptor> {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(&self.resolver) as Arc<dyn ResolvesServerCert>);
+        config.alpn_protocols = self.alpn_protocols.clone();
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    ///
+    /// Run the handshake hook, a connection is accepted if there is no hook
+    ///
+    pub(crate) fn accepts(&self, handshake: &TlsHandshake) -> bool {
+        match &self.handshake_hook {
+            Some(hook) => hook(handshake),
+            None => true,
+        }
+    }
+
+    ///
+    /// Reload the certificates whose files changed every `reload_interval`,
+    /// until the config is dropped
+    ///
+    pub(crate) fn watch(&self) {
+        let resolver = Arc::downgrade(&self.resolver);
+        tokio::spawn(watch(resolver, self.reload_interval));
+    }
+}
+
+///
+/// Choose the certificate of a connection from the server name the client sent (SNI)
+///
+/// Exact names are tried first, then wildcard names, then the default certificate.
+///
+#[derive(Debug)]
+struct CertResolver {
+    default: RwLock<Arc<CertifiedKey>>,
+    by_name: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+
+    /// the files of every certificate with its server name, None for the default one
+    files: Mutex<Vec<(Option<String>, CertFiles)>>,
+}
+
+impl CertResolver {
+    fn find(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        if let Some(server_name) = server_name.map(|name| name.to_ascii_lowercase()) {
+            let by_name = self.by_name.read().unwrap();
+            let wildcard = server_name
+                .split_once('.')
+                .map(|(_, parent)| format!("*.{}", parent));
+
+            let key = by_name
+                .get(&server_name)
+                .or_else(|| wildcard.and_then(|wildcard| by_name.get(&wildcard)));
+            if let Some(key) = key {
+                return Arc::clone(key);
+            }
+        }
+
+        Arc::clone(&self.default.read().unwrap())
+    }
+
+    ///
+    /// Load again the certificates whose files changed since they were loaded
+    ///
+    /// A certificate that can not be loaded (ex; the key file is not written yet) is kept
+    /// as it is, and loaded again when its files change again.
+    ///
+    fn reload(&self) {
+        let mut files = self.files.lock().unwrap();
+
+        for (server_name, files) in files.iter_mut() {
+            let (cert, key) = match (
+                std::fs::read(&files.cert_path),
+                std::fs::read(&files.key_path),
+            ) {
+                (Ok(cert), Ok(key)) => (cert, key),
+                (Err(e), _) | (_, Err(e)) => {
+                    eprintln!(
+                        "Failed to read the TLS certificate {:?}: {}",
+                        files.cert_path, e
+                    );
+                    continue;
+                }
+            };
+
+            if cert == files.cert && key == files.key_pem {
+                continue;
+            }
+
+            let reloaded = certified_key(&cert, &key);
+            files.cert = cert;
+            files.key_pem = key;
+
+            match reloaded {
+                Ok(key) => {
+                    let key = Arc::new(key);
+                    files.key = Arc::clone(&key);
+                    match server_name {
+                        Some(name) => {
+                            self.by_name.write().unwrap().insert(name.clone(), key);
+                        }
+                        None => *self.default.write().unwrap() = key,
+                    }
+                    println!("Reloaded the TLS certificate {:?}", files.cert_path);
+                }
+                Err(e) => eprintln!(
+                    "Failed to reload the TLS certificate {:?}: {}",
+                    files.cert_path, e
+                ),
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.find(client_hello.server_name()))
+    }
+}
+
+///
+/// A certificate with the files it was loaded from and their content,
+/// the content is compared to the files to find out if they changed
+///
+#[derive(Debug)]
+struct CertFiles {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    cert: Vec<u8>,
+    key_pem: Vec<u8>,
+    key: Arc<CertifiedKey>,
+}
+
+impl CertFiles {
+    fn load(cert_path: &Path, key_path: &Path) -> std::io::Result<Self> {
+        let cert = std::fs::read(cert_path)?;
+        let key_pem = std::fs::read(key_path)?;
+        let key = certified_key(&cert, &key_pem)?;
+
+        Ok(Self {
+            cert_path: cert_path.to_owned(),
+            key_path: key_path.to_owned(),
+            cert,
+            key_pem,
+            key: Arc::new(key),
+        })
+    }
+}
+
+///
+/// Build a certificate from a PEM certificate chain and a PEM private key,
+/// it fails if the key does not match the certificate
+///
+fn certified_key(cert_pem: &[u8], key_pem: &[u8]) -> std::io::Result<CertifiedKey> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let chain = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("invalid certificate: {}", e)))?;
+    if chain.is_empty() {
+        return Err(invalid("no certificate found".to_owned()));
+    }
+
+    let key = PrivateKeyDer::from_pem_slice(key_pem)
+        .map_err(|e| invalid(format!("invalid private key: {}", e)))?;
+
+    let provider: CryptoProvider = ring::default_provider();
+    CertifiedKey::from_der(chain, key, &provider).map_err(|e| invalid(e.to_string()))
+}
+
+/// Check the certificate files for changes every `interval` until the resolver is dropped
+async fn watch(resolver: Weak<CertResolver>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // the first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let Some(resolver) = resolver.upgrade() else {
+            return;
+        };
+        // reading the files is blocking IO
+        let _ = tokio::task::spawn_blocking(move || resolver.reload()).await;
+    }
+}